@@ -0,0 +1,122 @@
+//! Timings of the parse loop, the merge step and a whole multi threaded run, via `cargo bench`.
+//! criterion is not a dependency, so this reports the best and median of a fixed number of runs.
+
+use std::env::temp_dir;
+use std::fs;
+use std::hint::black_box;
+use std::time::Instant;
+
+use onebrc::generate::generate;
+use onebrc::{aggregate_file, aggregate_reader, AggregateConfig, City, Citymap};
+
+const ROWS: u64 = 1_000_000;
+const RUNS: usize = 10;
+/// Number of partial results the merge bench folds together, like the workers of a run would produce
+const PARTIALS: usize = 16;
+
+/// Times `run` over a fresh value from `setup`, so only `run` is measured
+fn bench<T>(name: &str, mut setup: impl FnMut() -> T, mut run: impl FnMut(T)) {
+    let mut times = (0..RUNS)
+        .map(|_| {
+            let input = setup();
+            let start = Instant::now();
+            run(input);
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    times.sort_unstable();
+    println!(
+        "{name:<20} best {:>10.2?}  median {:>10.2?}",
+        times[0],
+        times[RUNS / 2]
+    );
+}
+
+fn main() {
+    let path = temp_dir().join(format!("onebrc-bench-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    generate(ROWS, path, 42).unwrap();
+    let data = fs::read(path).unwrap();
+
+    bench(
+        "parse",
+        || (),
+        |_| {
+            black_box(aggregate_reader(&mut &data[..], &AggregateConfig::default()).unwrap());
+        },
+    );
+
+    let lines = data.split_inclusive(|&b| b == b'\n').collect::<Vec<_>>();
+    // The word at a time search against the byte loop it replaced, over the separator of every line
+    bench(
+        "separator memchr",
+        || (),
+        |_| {
+            for line in &lines {
+                black_box(onebrc::memchr(b';', line));
+            }
+        },
+    );
+    bench(
+        "separator bytewise",
+        || (),
+        |_| {
+            for line in &lines {
+                black_box(line.iter().position(|&b| b == b';'));
+            }
+        },
+    );
+
+    // The branchless parser of the fixed format against the generic byte loop, which takes over on trailing whitespace
+    let values = lines
+        .iter()
+        .map(|line| {
+            let value = line.rsplit(|&b| b == b';').next().unwrap();
+            value.strip_suffix(b"\n").unwrap_or(value)
+        })
+        .collect::<Vec<_>>();
+    let padded = values
+        .iter()
+        .map(|value| [value, &b" "[..]].concat())
+        .collect::<Vec<_>>();
+    bench("values fixed", City::default, |mut city| {
+        for value in &values {
+            city.add_new(value).unwrap();
+        }
+        black_box(city);
+    });
+    bench("values generic", City::default, |mut city| {
+        for value in &padded {
+            city.add_new(value).unwrap();
+        }
+        black_box(city);
+    });
+
+    let partials = lines
+        .chunks(lines.len().div_ceil(PARTIALS))
+        .map(|lines| {
+            aggregate_reader(&mut &lines.concat()[..], &AggregateConfig::default()).unwrap()
+        })
+        .collect::<Vec<_>>();
+    bench(
+        "merge",
+        || partials.clone(),
+        |partials| {
+            let mut merged = Citymap::new();
+            for partial in partials {
+                merged.merge_with(partial);
+            }
+            black_box(merged);
+        },
+    );
+
+    bench(
+        "end to end",
+        || (),
+        |_| {
+            black_box(aggregate_file(path, &AggregateConfig::new()).unwrap());
+        },
+    );
+
+    fs::remove_file(path).unwrap();
+}
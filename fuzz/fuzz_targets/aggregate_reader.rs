@@ -0,0 +1,10 @@
+//! Arbitrary bytes through the line parser, which must report malformed input as errors instead of panicking.
+//! Run with `cargo +nightly fuzz run aggregate_reader`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = onebrc::aggregate_reader(&mut &data[..], &onebrc::AggregateConfig::default());
+});
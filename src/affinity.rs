@@ -0,0 +1,28 @@
+//! Binding the current thread to a logical CPU, through libc on Linux and a no-op elsewhere.
+
+/// Restricts the calling thread to logical CPU `core`.
+/// Failures, such as a CPU that does not exist, leave the thread free to run anywhere.
+#[cfg(target_os = "linux")]
+pub fn pin_to_core(core: usize) {
+    use std::ffi::c_int;
+
+    /// Matches glibc's fixed size `cpu_set_t` of 1024 bits
+    const CPU_SET_WORDS: usize = 1024 / 64;
+
+    extern "C" {
+        fn sched_setaffinity(pid: c_int, cpusetsize: usize, mask: *const u64) -> c_int;
+    }
+
+    if core >= CPU_SET_WORDS * 64 {
+        return;
+    }
+    let mut mask = [0u64; CPU_SET_WORDS];
+    mask[core / 64] |= 1 << (core % 64);
+    // A pid of 0 targets the calling thread
+    unsafe {
+        sched_setaffinity(0, size_of_val(&mask), mask.as_ptr());
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_core(_core: usize) {}
@@ -0,0 +1,55 @@
+//! Compressed inputs, decoded by piping them through the system's command line decompressor.
+//! Compressed streams cannot be split at arbitrary offsets, so they are always parsed by a single worker.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// Decompressor program and leading magic bytes of every supported format
+const FORMATS: &[(&str, &[u8])] = &[
+    #[cfg(feature = "gzip")]
+    ("gzip", &[0x1f, 0x8b]),
+    #[cfg(feature = "zstd")]
+    ("zstd", &[0x28, 0xb5, 0x2f, 0xfd]),
+];
+
+/// Program decompressing `path`, detected from the magic bytes so that the extension does not matter
+pub fn decompressor(path: &str) -> Option<&'static str> {
+    let mut magic = [0; 4];
+    let len = File::open(path).ok()?.read(&mut magic).ok()?;
+    FORMATS
+        .iter()
+        .find(|(_, prefix)| magic[..len].starts_with(prefix))
+        .map(|&(program, _)| program)
+}
+
+/// Starts `program` writing the decompressed contents of `path` to its piped stdout
+fn spawn(program: &str, path: &str) -> io::Result<Child> {
+    Command::new(program)
+        .args(["-dc", "--", path])
+        .stdout(Stdio::piped())
+        .spawn()
+}
+
+/// Runs `read` over the decompressed contents of `path` that `program` writes to its stdout, then waits for it.
+/// `read` also tells whether it read to the end, as otherwise the decompressor may still be writing and is
+/// killed instead, its exit status then saying nothing. Errors of `read` take precedence over those of `program`.
+pub fn read_with<T, E: From<io::Error>>(
+    program: &str,
+    path: &str,
+    read: impl FnOnce(ChildStdout) -> (Result<T, E>, bool),
+) -> Result<T, E> {
+    let mut child = spawn(program, path)
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run {program}: {e}")))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let (result, complete) = read(stdout);
+    if !complete {
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    let value = result?;
+    if complete && !status.success() {
+        return Err(io::Error::other(format!("{program} failed on {path:?}: {status}")).into());
+    }
+    Ok(value)
+}
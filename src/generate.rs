@@ -0,0 +1,159 @@
+//! Synthetic measurement files in the exact `city;temp` line format the parser expects.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Stations with their mean temperature, taken from the 1BRC reference generator
+const STATIONS: &[(&str, f64)] = &[
+    ("Abha", 18.0),
+    ("Abidjan", 26.0),
+    ("Abéché", 29.4),
+    ("Accra", 26.4),
+    ("Addis Ababa", 16.0),
+    ("Adelaide", 17.3),
+    ("Albuquerque", 14.0),
+    ("Alexandria", 20.0),
+    ("Algiers", 18.2),
+    ("Almaty", 10.0),
+    ("Amsterdam", 10.2),
+    ("Anadyr", -6.9),
+    ("Anchorage", 2.8),
+    ("Ankara", 12.0),
+    ("Ashgabat", 17.1),
+    ("Athens", 19.2),
+    ("Auckland", 15.2),
+    ("Baghdad", 22.8),
+    ("Bangkok", 28.6),
+    ("Barcelona", 18.2),
+    ("Beijing", 12.9),
+    ("Beirut", 20.9),
+    ("Belgrade", 12.5),
+    ("Bergen", 7.7),
+    ("Berlin", 10.3),
+    ("Bishkek", 11.3),
+    ("Boston", 10.9),
+    ("Bratislava", 10.5),
+    ("Bridgetown", 27.0),
+    ("Brisbane", 21.4),
+    ("Brussels", 10.5),
+    ("Bucharest", 10.8),
+    ("Budapest", 11.3),
+    ("Bulawayo", 18.9),
+    ("Cairo", 21.4),
+    ("Calgary", 4.4),
+    ("Cape Town", 16.2),
+    ("Chicago", 9.8),
+    ("Chișinău", 10.2),
+    ("Colombo", 27.4),
+    ("Conakry", 26.4),
+    ("Copenhagen", 9.1),
+    ("Cracow", 9.3),
+    ("Dakar", 24.0),
+    ("Dallas", 19.0),
+    ("Darwin", 27.6),
+    ("Denver", 10.4),
+    ("Dhaka", 25.9),
+    ("Dikson", -11.1),
+    ("Dubai", 26.9),
+    ("Dublin", 9.8),
+    ("Edinburgh", 9.3),
+    ("Fairbanks", -2.3),
+    ("Frankfurt", 10.6),
+    ("Gjoa Haven", -14.4),
+    ("Hamburg", 9.7),
+    ("Hanoi", 23.6),
+    ("Havana", 25.2),
+    ("Helsinki", 5.9),
+    ("Hong Kong", 23.3),
+    ("Honolulu", 25.4),
+    ("Iqaluit", -9.3),
+    ("Istanbul", 13.9),
+    ("İzmir", 17.9),
+    ("Jakarta", 26.7),
+    ("Jerusalem", 18.3),
+    ("Kabul", 12.1),
+    ("Karachi", 26.0),
+    ("Kathmandu", 18.3),
+    ("Khartoum", 29.9),
+    ("Kyiv", 8.4),
+    ("Lagos", 26.8),
+    ("Lhasa", 7.6),
+    ("Lisbon", 17.5),
+    ("Lomé", 26.9),
+    ("London", 11.3),
+    ("Los Angeles", 18.6),
+    ("Madrid", 15.0),
+    ("Manila", 28.4),
+    ("Mexico City", 17.5),
+    ("Moscow", 5.8),
+    ("Mumbai", 27.1),
+    ("Nairobi", 17.8),
+    ("New Delhi", 25.0),
+    ("New York City", 12.9),
+    ("Nuuk", -1.4),
+    ("Oslo", 5.7),
+    ("Palembang", 27.3),
+    ("Paris", 12.3),
+    ("Prague", 8.4),
+    ("Reykjavík", 4.3),
+    ("Riyadh", 26.0),
+    ("Rome", 15.2),
+    ("Roseau", 26.2),
+    ("San Francisco", 14.6),
+    ("São Paulo", 19.7),
+    ("Seoul", 12.5),
+    ("Singapore", 27.0),
+    ("St. John's", 5.0),
+    ("Stockholm", 6.6),
+    ("Sydney", 17.7),
+    ("Tokyo", 15.4),
+    ("Toronto", 9.4),
+    ("Ulaanbaatar", -0.4),
+    ("Vancouver", 10.4),
+    ("Vienna", 10.4),
+    ("Warsaw", 8.5),
+    ("Washington, D.C.", 14.6),
+    ("Yakutsk", -8.8),
+    ("Zürich", 9.3),
+];
+
+/// splitmix64, good enough statistically and trivially seedable
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0.0..1.0`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal distribution via Box-Muller
+    fn next_gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
+
+/// Writes `rows` measurements to `out`, sampled around each station's mean with a standard deviation of 10 degrees
+pub fn generate(rows: u64, out: &str, seed: u64) -> io::Result<()> {
+    let mut rng = Rng(seed);
+    let mut writer = BufWriter::with_capacity(10_usize.pow(6), File::create(out)?);
+    for _ in 0..rows {
+        let (city, mean) = STATIONS[(rng.next_u64() % STATIONS.len() as u64) as usize];
+        // Tenths of a degree, within the value range the challenge allows
+        let temp = ((mean + rng.next_gaussian() * 10.0) * 10.0).round() as i64;
+        let temp = temp.clamp(-999, 999);
+        let sign = if temp < 0 { "-" } else { "" };
+        let abs = temp.abs();
+        writeln!(writer, "{city};{sign}{}.{}", abs / 10, abs % 10)?;
+    }
+    writer.flush()
+}
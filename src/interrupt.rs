@@ -0,0 +1,42 @@
+//! Turning SIGINT into a stop request, through libc on unix and a no-op elsewhere.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Makes the first SIGINT set `stop` instead of killing the process.
+/// A second one kills it as usual, in case stopping takes too long.
+#[cfg(unix)]
+pub fn install(stop: Arc<AtomicBool>) {
+    use std::ffi::c_int;
+    use std::sync::atomic::Ordering;
+    use std::sync::OnceLock;
+
+    const SIGINT: c_int = 2;
+    const SIG_DFL: usize = 0;
+
+    /// The flag the handler sets, which cannot be handed to it any other way
+    static STOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+    extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_interrupt(_: c_int) {
+        // Reading an initialized OnceLock, a relaxed store and signal itself are all async-signal-safe
+        if let Some(stop) = STOP.get() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    if STOP.set(stop).is_ok() {
+        unsafe {
+            signal(SIGINT, on_interrupt as extern "C" fn(c_int) as usize);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install(_: Arc<AtomicBool>) {}
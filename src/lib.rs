@@ -0,0 +1,2040 @@
+//! Aggregation engine for the one billion row challenge: per city min/mean/max over `city;value` lines.
+
+#[cfg(feature = "affinity")]
+mod affinity;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod compressed;
+#[cfg(feature = "fxhash")]
+mod fxhash;
+pub mod generate;
+#[cfg(feature = "interrupt")]
+mod interrupt;
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+mod mmap;
+mod partial;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, stdin, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::{Neg, Range};
+use std::panic::resume_unwind;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::{available_parallelism, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+use mmap::Mmap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct City {
+    min: i64,
+    max: i64,
+    /// Wider than the values so that no realistic row count can overflow it, see [`MAX_ABS_VALUE`]
+    sum: i128,
+    /// Kept as an exact integer so that merged partial results agree with a single pass
+    sum_squares: i128,
+    occurrences: u64,
+    /// Occurrences per bucket of `bucket_width` tenths of a degree, see [`HISTOGRAM_MIN`]
+    #[cfg(feature = "percentiles")]
+    histogram: Box<[u64]>,
+    /// Tenths per bucket, fixed at creation so that a city always knows how to read its own histogram
+    #[cfg(feature = "percentiles")]
+    bucket_width: i64,
+}
+
+/// Lowest value in tenths tracked by the histogram, values beyond either end land in the outermost bucket
+#[cfg(feature = "percentiles")]
+const HISTOGRAM_MIN: i64 = -999;
+#[cfg(feature = "percentiles")]
+const HISTOGRAM_MAX: i64 = 999;
+
+/// Buckets of a histogram covering [`HISTOGRAM_MIN`] to [`HISTOGRAM_MAX`] in steps of `width`
+#[cfg(feature = "percentiles")]
+pub(crate) fn histogram_buckets(width: i64) -> usize {
+    ((HISTOGRAM_MAX - HISTOGRAM_MIN + width) / width) as usize
+}
+
+/// Reasons a single value fails to parse, without the surrounding line context
+#[derive(Copy, Clone, Debug)]
+pub enum ValueError {
+    BadDigit(u8),
+    Empty,
+    OutOfRange,
+}
+
+/// Largest magnitude in tenths accepted by the parser, about 303 million degrees, so that only digit runs
+/// no export would write are rejected. Its square still fits an `i64`, so even `u64::MAX` values at this bound
+/// cannot overflow `sum` or `sum_squares`.
+pub const MAX_ABS_VALUE: i64 = 3_037_000_499;
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The line contains no `;` between city and value
+    MissingSeparator { offset: u64, line: String },
+    /// The city name is not valid UTF-8
+    InvalidUtf8 { offset: u64, line: String },
+    /// The line contains more than one separator
+    ExtraSeparator { offset: u64, line: String },
+    /// The value contains a byte other than digits, a leading sign and one decimal point followed by at most one digit
+    BadDigit { offset: u64, line: String, byte: u8 },
+    /// The value contains no digits at all
+    EmptyValue { offset: u64, line: String },
+    /// The value exceeds [`MAX_ABS_VALUE`] in magnitude
+    OutOfRange { offset: u64, line: String },
+    /// Opening or reading the input failed
+    Io(io::Error),
+    /// Worker `thread` of a multi threaded run failed on a line within the aligned byte `range`
+    Worker {
+        thread: usize,
+        range: Range<u64>,
+        source: Box<ParseError>,
+    },
+}
+
+impl ParseError {
+    /// Attributes the error to worker `thread` aggregating `range`
+    fn in_worker(self, thread: usize, range: Range<u64>) -> Self {
+        ParseError::Worker {
+            thread,
+            range,
+            source: Box::new(self),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::MissingSeparator { offset, line } => {
+                write!(f, "missing separator in line at byte {offset}: {line:?}")
+            }
+            ParseError::InvalidUtf8 { offset, line } => {
+                write!(
+                    f,
+                    "city name is not valid UTF-8 in line at byte {offset}: {line:?}"
+                )
+            }
+            ParseError::ExtraSeparator { offset, line } => {
+                write!(
+                    f,
+                    "more than one separator in line at byte {offset}: {line:?}"
+                )
+            }
+            ParseError::BadDigit { offset, line, byte } => write!(
+                f,
+                "encountered {:?} in value of line at byte {offset}: {line:?}",
+                char::from(*byte)
+            ),
+            ParseError::EmptyValue { offset, line } => {
+                write!(f, "empty value in line at byte {offset}: {line:?}")
+            }
+            ParseError::OutOfRange { offset, line } => {
+                write!(f, "value out of range in line at byte {offset}: {line:?}")
+            }
+            ParseError::Io(e) => write!(f, "{e}"),
+            ParseError::Worker {
+                thread,
+                range,
+                source,
+            } => write!(f, "thread {thread} range {range:?}: {source}"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            ParseError::Worker { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
+/// Parses the `-?\d?\d\.\d` format the challenge guarantees into tenths, without branching per byte.
+/// Returns `None` for anything else so the caller can fall back to the generic parser.
+fn parse_fixed(input: &[u8]) -> Option<i64> {
+    let neg = input.first() == Some(&b'-');
+    let digits = &input[neg as usize..];
+    // `long` selects between the `d.d` and `dd.d` layouts
+    let long = match digits.len() {
+        3 => 0,
+        4 => 1,
+        _ => return None,
+    };
+    let tens = digits[0].wrapping_sub(b'0');
+    let ones = digits[long].wrapping_sub(b'0');
+    let tenths = digits[long + 2].wrapping_sub(b'0');
+    if digits[long + 1] != b'.' || tens.max(ones).max(tenths) > 9 {
+        return None;
+    }
+    let val = (tens as i64 * long as i64) * 100 + ones as i64 * 10 + tenths as i64;
+    // Negates without a branch, as 1 - 2 * neg is either 1 or -1
+    Some(val * (1 - 2 * neg as i64))
+}
+
+/// Parses a value into tenths, taking the fast path whenever it is in the format the challenge guarantees
+fn parse_value(input: &[u8]) -> Result<i64, ValueError> {
+    match parse_fixed(input) {
+        Some(val) => Ok(val),
+        None => parse_generic(input),
+    }
+}
+
+/// Byte by byte fallback for values outside the fixed format
+#[cold]
+fn parse_generic(input: &[u8]) -> Result<i64, ValueError> {
+    let mut val = 0;
+    let mut has_digits = false;
+    // Digits after the decimal point, `None` while there is none
+    let mut decimals = None;
+    // Tolerates CRLF line endings and stray surrounding whitespace
+    let input = input.trim_ascii();
+    // Some exports write an explicit sign on positive values
+    let (is_neg, input) = match input {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, input),
+    };
+    for &char in input {
+        match char {
+            // Values are stored in tenths, a second decimal could only be dropped silently
+            b'0'..=b'9' if decimals != Some(1) => {
+                val *= 10;
+                let digit = char - b'0';
+                val += digit as i64;
+                has_digits = true;
+                if let Some(decimals) = &mut decimals {
+                    *decimals += 1;
+                }
+                // Bails before a long run of digits can overflow
+                if val > MAX_ABS_VALUE * 10 {
+                    return Err(ValueError::OutOfRange);
+                }
+            }
+            b'.' if decimals.is_none() => decimals = Some(0),
+            _ => return Err(ValueError::BadDigit(char)),
+        }
+    }
+    if !has_digits {
+        return Err(ValueError::Empty);
+    }
+    // Integer values like `30` or `30.` are whole degrees, not tenths
+    if decimals != Some(1) {
+        val *= 10;
+    }
+    if val > MAX_ABS_VALUE {
+        return Err(ValueError::OutOfRange);
+    }
+    if is_neg {
+        val = val.neg();
+    }
+    Ok(val)
+}
+
+impl City {
+    /// An empty city counting values in histogram buckets of `tenths` tenths of a degree,
+    /// see [`AggregateConfig::histogram_resolution`]. Only has an effect with the `percentiles` feature.
+    pub fn with_histogram_resolution(tenths: u32) -> Self {
+        assert!(tenths > 0, "histogram buckets must span at least a tenth");
+        #[cfg(not(feature = "percentiles"))]
+        let _ = tenths;
+        Self {
+            min: i64::MAX,
+            max: i64::MIN,
+            sum: 0,
+            sum_squares: 0,
+            occurrences: 0,
+            #[cfg(feature = "percentiles")]
+            histogram: vec![0; histogram_buckets(tenths as i64)].into_boxed_slice(),
+            #[cfg(feature = "percentiles")]
+            bucket_width: tenths as i64,
+        }
+    }
+    pub fn add_new(&mut self, input: &[u8]) -> Result<(), ValueError> {
+        self.add_new_value(parse_value(input)?);
+        Ok(())
+    }
+
+    /// Adds a value in tenths, which must not exceed [`MAX_ABS_VALUE`] in magnitude
+    pub fn add_new_value(&mut self, new: i64) {
+        debug_assert!(new.abs() <= MAX_ABS_VALUE, "value {new} out of range");
+        self.min = self.min.min(new);
+        self.max = self.max.max(new);
+        self.sum += new as i128;
+        self.sum_squares += (new * new) as i128;
+        self.occurrences += 1;
+        #[cfg(feature = "percentiles")]
+        {
+            let offset = new.clamp(HISTOGRAM_MIN, HISTOGRAM_MAX) - HISTOGRAM_MIN;
+            self.histogram[(offset / self.bucket_width) as usize] += 1;
+        }
+    }
+    pub fn min(&self) -> f64 {
+        self.min_in(Unit::Celsius)
+    }
+    pub fn min_in(&self, unit: Unit) -> f64 {
+        if self.is_empty() {
+            return f64::NAN;
+        }
+        unit.convert(self.min as i128, 1)
+    }
+    /// Rounded to one decimal, with halves rounded towards positive infinity like the 1BRC reference.
+    /// Exact however large the sum grows: tenths are summed as integers and divided once in integer arithmetic,
+    /// where a running floating point mean such as Welford's would only add rounding errors.
+    /// NaN for a city without values, like the minimum and maximum.
+    pub fn mean(&self) -> f64 {
+        self.mean_in(Unit::Celsius)
+    }
+    pub fn mean_in(&self, unit: Unit) -> f64 {
+        if self.is_empty() {
+            return f64::NAN;
+        }
+        unit.convert(self.sum, self.occurrences as i128)
+    }
+    pub fn max(&self) -> f64 {
+        self.max_in(Unit::Celsius)
+    }
+    pub fn max_in(&self, unit: Unit) -> f64 {
+        if self.is_empty() {
+            return f64::NAN;
+        }
+        unit.convert(self.max as i128, 1)
+    }
+    pub fn count(&self) -> u64 {
+        self.occurrences
+    }
+    /// Population standard deviation
+    pub fn std(&self) -> f64 {
+        self.std_in(Unit::Celsius)
+    }
+    pub fn std_in(&self, unit: Unit) -> f64 {
+        let n = self.occurrences as i128;
+        // n² times the variance, computed exactly to avoid the cancellation in E[x²] - E[x]²
+        let scaled = n
+            .checked_mul(self.sum_squares)
+            .zip(self.sum.checked_mul(self.sum))
+            .map(|(lhs, rhs)| lhs - rhs);
+        let variance = match scaled {
+            Some(scaled) => scaled as f64 / (n as f64).powi(2),
+            // Beyond ~10^15 values the exact form overflows, where rounding errors matter far less
+            None => {
+                let mean = self.sum as f64 / n as f64;
+                (self.sum_squares as f64 / n as f64 - mean * mean).max(0.0)
+            }
+        };
+        variance.sqrt() / 10.0 * unit.scale()
+    }
+    /// Smallest value such that at least `p` (within `0.0..=1.0`) of all values are less or equal to it.
+    /// With buckets coarser than a tenth this is the top of the bucket holding it, so at most a bucket too high.
+    /// NaN for a city without values.
+    #[cfg(feature = "percentiles")]
+    pub fn percentile(&self, p: f64) -> f64 {
+        self.percentile_in(p, Unit::Celsius)
+    }
+    #[cfg(feature = "percentiles")]
+    pub fn percentile_in(&self, p: f64, unit: Unit) -> f64 {
+        if self.is_empty() {
+            return f64::NAN;
+        }
+        let rank = ((p * self.occurrences as f64).ceil() as u64).clamp(1, self.occurrences);
+        let mut seen = 0;
+        for (i, &count) in self.histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let top = HISTOGRAM_MIN + (i as i64 + 1) * self.bucket_width - 1;
+                return unit.convert(top.clamp(self.min, self.max) as i128, 1);
+            }
+        }
+        self.max_in(unit)
+    }
+    /// Most frequent value, the lowest one if several are equally frequent.
+    /// With buckets coarser than a tenth this is the middle of the most frequent bucket instead.
+    /// NaN for a city without values.
+    #[cfg(feature = "percentiles")]
+    pub fn mode_in(&self, unit: Unit) -> f64 {
+        if self.is_empty() {
+            return f64::NAN;
+        }
+        // max_by_key picks the last maximum, so search from the top to end up with the lowest
+        let (i, _) = self
+            .histogram
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|&(_, &count)| count)
+            .unwrap();
+        let middle = HISTOGRAM_MIN + i as i64 * self.bucket_width + (self.bucket_width - 1) / 2;
+        unit.convert(middle.clamp(self.min, self.max) as i128, 1)
+    }
+
+    pub fn add_result(&mut self, other: Self) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+        self.occurrences += other.occurrences;
+        #[cfg(feature = "percentiles")]
+        {
+            assert_eq!(
+                self.bucket_width, other.bucket_width,
+                "merged cities of different histogram resolutions"
+            );
+            for (lhs, rhs) in self.histogram.iter_mut().zip(other.histogram.iter()) {
+                *lhs += rhs;
+            }
+        }
+        self.debug_check();
+    }
+    /// Catches merges of corrupted partial results early in debug builds
+    fn debug_check(&self) {
+        debug_assert!(
+            self.occurrences == 0 || self.min <= self.max,
+            "city with {} values has min {} above max {}",
+            self.occurrences,
+            self.min,
+            self.max
+        );
+    }
+    /// True for a city that has not seen a single value, whose statistics are meaningless
+    pub fn is_empty(&self) -> bool {
+        self.occurrences == 0
+    }
+    pub fn stats(&self) -> CityStats {
+        self.stats_in(Unit::Celsius)
+    }
+    pub fn stats_in(&self, unit: Unit) -> CityStats {
+        CityStats {
+            min: self.min_in(unit),
+            mean: self.mean_in(unit),
+            max: self.max_in(unit),
+            count: self.count(),
+        }
+    }
+}
+
+/// Unit statistics are reported in, values are always stored as tenths of a degree Celsius
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Unit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl Unit {
+    /// Converts `tenths / count` tenths of a degree Celsius, rounded to one decimal with halves towards positive infinity
+    fn convert(self, tenths: i128, count: i128) -> f64 {
+        // The exact converted value in tenths as a fraction, so that rounding happens once after the conversion
+        let (num, den) = match self {
+            Unit::Celsius => (tenths, count),
+            Unit::Fahrenheit => (9 * tenths + 1600 * count, 5 * count),
+        };
+        // floor(num / den + 0.5) in exact integer arithmetic, so `{:.1}` never sees a tie
+        (2 * num + den).div_euclid(2 * den) as f64 / 10.0
+    }
+    /// Factor between differences of temperatures in Celsius and in this unit
+    fn scale(self) -> f64 {
+        match self {
+            Unit::Celsius => 1.0,
+            Unit::Fahrenheit => 1.8,
+        }
+    }
+}
+
+/// `min/mean/max` in degrees Celsius, with one decimal unless the formatter asks for another precision.
+/// A city without values shows as `NaN/NaN/NaN`.
+impl Display for City {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let precision = f.precision().unwrap_or(1);
+        write!(
+            f,
+            "{:.precision$}/{:.precision$}/{:.precision$}",
+            self.min(),
+            self.mean(),
+            self.max()
+        )
+    }
+}
+
+/// The reported statistics of a [`City`], rounded to one decimal like the text output, in degrees Celsius unless
+/// taken with [`City::stats_in`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CityStats {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+    pub count: u64,
+}
+
+/// A city with histogram buckets of a tenth, which keep percentiles and the mode exact
+impl Default for City {
+    fn default() -> Self {
+        Self::with_histogram_resolution(1)
+    }
+}
+
+/// Slots allocated on first insertion, enough for the ~10k station variant at half load
+const INITIAL_SLOTS: usize = 1 << 15;
+
+/// Stations the maps aggregating an input are sized for up front, as many as the 10k station variant has
+pub const EXPECTED_STATIONS: usize = 10_000;
+
+/// Station name storage of a [`Citymap`]
+pub trait Key<'a>: Sized {
+    /// Called once per station, when it is first encountered.
+    /// Returns `None` for names that are not valid UTF-8, unless the `unsafe` feature skips that check.
+    fn from_name(name: &'a [u8]) -> Option<Self>;
+    /// Called for new names that [`Key::from_name`] rejects when [`LineFormat::lossy_names`] is set
+    fn from_lossy(name: &'a [u8]) -> Self;
+    fn name(&self) -> &[u8];
+}
+
+impl Key<'_> for String {
+    fn from_name(name: &[u8]) -> Option<Self> {
+        name_to_str(name).map(str::to_owned)
+    }
+    /// Replaces invalid sequences with U+FFFD, so distinct raw names may end up as the same key
+    fn from_lossy(name: &[u8]) -> Self {
+        String::from_utf8_lossy(name).into_owned()
+    }
+    fn name(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+/// Borrows names straight out of an input that outlives the map, avoiding an allocation per station
+impl<'a> Key<'a> for &'a [u8] {
+    /// Validated here already so that converting to owned names later cannot fail
+    fn from_name(name: &'a [u8]) -> Option<Self> {
+        name_to_str(name).map(|_| name)
+    }
+    /// Keeps the raw bytes, which [`Citymap::into_owned`] replaces invalid sequences in
+    fn from_lossy(name: &'a [u8]) -> Self {
+        name
+    }
+    fn name(&self) -> &[u8] {
+        self
+    }
+}
+
+fn name_to_str(name: &[u8]) -> Option<&str> {
+    #[cfg(not(feature = "unsafe"))]
+    let name = std::str::from_utf8(name).ok()?;
+
+    #[cfg(feature = "unsafe")]
+    let name = unsafe { std::str::from_utf8_unchecked(name) };
+
+    Some(name)
+}
+
+#[derive(Clone, Debug)]
+pub struct Citymap<K = String> {
+    /// Open addressed with linear probing, the length is always zero or a power of two
+    slots: Vec<Option<Slot<K>>>,
+    len: usize,
+    /// Values outside of [`LineFormat::valid_range`]
+    out_of_range: u64,
+    /// Tenths per histogram bucket of the cities the map creates, see [`AggregateConfig::histogram_resolution`]
+    histogram_resolution: u32,
+    /// Scratch space of [`Citymap::lookup_lossy`] for invalid names with their invalid sequences replaced
+    replaced: String,
+}
+
+#[derive(Clone, Debug)]
+struct Slot<K> {
+    /// [`hash_name`] of `name`, so probing compares names only on a full hash match and growing or merging never rehashes
+    hash: u64,
+    name: K,
+    city: City,
+}
+
+/// Maps are equal if they hold the same cities, regardless of where they sit in the table
+impl<'a, K: Key<'a>> PartialEq for Citymap<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self.out_of_range == other.out_of_range
+            && self
+                .slots
+                .iter()
+                .flatten()
+                .all(|slot| other.get_city(slot.name.name()) == Some(&slot.city))
+    }
+}
+
+impl<K> Default for Citymap<K> {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            len: 0,
+            out_of_range: 0,
+            histogram_resolution: 1,
+            replaced: String::new(),
+        }
+    }
+}
+
+/// Full width hash over the whole name
+fn hash_name(name: &[u8]) -> u64 {
+    // Station keys are tiny and not adversarial, so FxHash is a cheaper alternative
+    #[cfg(feature = "fxhash")]
+    {
+        use std::hash::Hasher;
+        let mut hasher = fxhash::FxHasher::default();
+        hasher.write(name);
+        hasher.finish()
+    }
+    // FNV-1a
+    #[cfg(not(feature = "fxhash"))]
+    {
+        name.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+impl<K> Citymap<K> {
+    /// The city named `lookup`, inserted if it is new.
+    /// Returns `None` if a new name cannot be turned into a key, see [`Key::from_name`].
+    pub fn lookup<'a>(&mut self, lookup: &'a [u8]) -> Option<&mut City>
+    where
+        K: Key<'a>,
+    {
+        let hash = hash_name(lookup);
+        let i = match self.find(lookup, hash) {
+            Ok(i) => i,
+            // The name is only converted into a key when the slot is vacant
+            Err(i) => {
+                let name = K::from_name(lookup)?;
+                let city = City::with_histogram_resolution(self.histogram_resolution);
+                self.insert(i, Slot { hash, name, city })
+            }
+        };
+        self.slots[i].as_mut().map(|slot| &mut slot.city)
+    }
+    /// [`Citymap::lookup`] that stores new names which are no valid key under [`Key::from_lossy`] instead
+    pub fn lookup_lossy<'a>(&mut self, lookup: &'a [u8]) -> &mut City
+    where
+        K: Key<'a>,
+    {
+        let hash = hash_name(lookup);
+        let i = match self.find(lookup, hash) {
+            Ok(i) => i,
+            Err(i) => match K::from_name(lookup) {
+                Some(name) => {
+                    let city = City::with_histogram_resolution(self.histogram_resolution);
+                    self.insert(i, Slot { hash, name, city })
+                }
+                None => self.find_replaced(lookup),
+            },
+        };
+        &mut self.slots[i].as_mut().unwrap().city
+    }
+    /// Index of the slot of the invalid name `lookup`, which owned keys only hold with invalid sequences replaced.
+    /// The replaced name is built in a buffer the map reuses, so known names cost a second probe but no allocation.
+    fn find_replaced<'a>(&mut self, lookup: &'a [u8]) -> usize
+    where
+        K: Key<'a>,
+    {
+        self.replaced.clear();
+        for chunk in lookup.utf8_chunks() {
+            self.replaced.push_str(chunk.valid());
+            if !chunk.invalid().is_empty() {
+                self.replaced.push(char::REPLACEMENT_CHARACTER);
+            }
+        }
+        let replaced = self.replaced.as_bytes();
+        if let Ok(i) = self.find(replaced, hash_name(replaced)) {
+            return i;
+        }
+        let name = K::from_lossy(lookup);
+        let hash = hash_name(name.name());
+        match self.find(name.name(), hash) {
+            Ok(i) => i,
+            Err(i) => {
+                let city = City::with_histogram_resolution(self.histogram_resolution);
+                self.insert(i, Slot { hash, name, city })
+            }
+        }
+    }
+    /// Index of the slot holding `lookup` hashing to `hash`, or of the vacant slot it belongs in,
+    /// or `Err(0)` while nothing is allocated.
+    fn find<'a>(&self, lookup: &[u8], hash: u64) -> Result<usize, usize>
+    where
+        K: Key<'a>,
+    {
+        if self.slots.is_empty() {
+            return Err(0);
+        }
+        let mask = self.slots.len() - 1;
+        let mut i = hash as usize & mask;
+        loop {
+            match &self.slots[i] {
+                Some(slot) if slot.hash == hash && slot.name.name() == lookup => return Ok(i),
+                Some(_) => i = (i + 1) & mask,
+                None => return Err(i),
+            }
+        }
+    }
+    /// Stores the new `slot` in the vacant slot `i` that [`Citymap::find`] returned for it, returning the index
+    /// it ends up at. Only insertions grow the map, so lookups of names it holds already never move it.
+    fn insert<'a>(&mut self, mut i: usize, slot: Slot<K>) -> usize
+    where
+        K: Key<'a>,
+    {
+        // Keep the load factor at or below one half so probe sequences stay short
+        if (self.len + 1) * 2 > self.slots.len() {
+            self.grow();
+            i = self.find(slot.name.name(), slot.hash).unwrap_err();
+        }
+        self.slots[i] = Some(slot);
+        self.len += 1;
+        i
+    }
+    fn grow(&mut self) {
+        let size = (self.slots.len() * 2).max(INITIAL_SLOTS);
+        let mut old = std::mem::take(&mut self.slots);
+        self.slots.resize_with(size, || None);
+        let mask = size - 1;
+        for slot in old.drain(..).flatten() {
+            let mut i = slot.hash as usize & mask;
+            while self.slots[i].is_some() {
+                i = (i + 1) & mask;
+            }
+            self.slots[i] = Some(slot);
+        }
+    }
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A map with room for `stations` cities before it has to grow, allocated right away
+    pub fn with_capacity(stations: usize) -> Self {
+        let mut map = Self::new();
+        // Growing happens once an insertion would take the load factor past one half, so at least `stations` fit
+        if stations > 0 {
+            map.slots
+                .resize_with((stations * 2).next_power_of_two(), || None);
+        }
+        map
+    }
+    /// The map with new cities counting values in histogram buckets of `tenths` tenths of a degree,
+    /// see [`City::with_histogram_resolution`]. Cities already in the map keep theirs.
+    pub fn with_histogram_resolution(mut self, tenths: u32) -> Self {
+        assert!(tenths > 0, "histogram buckets must span at least a tenth");
+        self.histogram_resolution = tenths;
+        self
+    }
+    /// Tenths per histogram bucket of the cities the map creates, 1 unless set otherwise
+    pub fn histogram_resolution(&self) -> u32 {
+        self.histogram_resolution
+    }
+    /// Number of cities the map holds at most before it has to grow
+    pub fn capacity(&self) -> usize {
+        self.slots.len() / 2
+    }
+    /// Statistics of the station `name` as reported, `None` if it has no values.
+    /// The read-only companion of [`Citymap::lookup`], probing for one name instead of going through all of them.
+    pub fn get<'a>(&self, name: &str) -> Option<CityStats>
+    where
+        K: Key<'a>,
+    {
+        self.get_city(name.as_bytes())
+            .filter(|city| !city.is_empty())
+            .map(City::stats)
+    }
+    /// The city named `name`, if it has been looked up before
+    pub fn get_city<'a>(&self, name: &[u8]) -> Option<&City>
+    where
+        K: Key<'a>,
+    {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let hash = hash_name(name);
+        let mask = self.slots.len() - 1;
+        let mut i = hash as usize & mask;
+        loop {
+            match &self.slots[i] {
+                Some(slot) if slot.hash == hash && slot.name.name() == name => {
+                    return Some(&slot.city)
+                }
+                Some(_) => i = (i + 1) & mask,
+                None => return None,
+            }
+        }
+    }
+    /// Number of distinct cities
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Number of values that were aggregated despite lying outside of [`LineFormat::valid_range`]
+    pub fn out_of_range(&self) -> u64 {
+        self.out_of_range
+    }
+    /// Number of values aggregated over all cities
+    pub fn rows(&self) -> u64 {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| slot.city.count())
+            .sum()
+    }
+    /// Every city with its name, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &City)> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| (&slot.name, &slot.city))
+    }
+    pub fn into_key_values(self) -> Vec<(K, City)> {
+        self.into_iter().collect()
+    }
+    /// [`Extend::extend`] with the cities of `rhs`, also adding up the out of range counts.
+    /// Reuses the hashes `rhs` already holds instead of hashing every name again.
+    pub fn merge_with<'a>(&mut self, rhs: Self)
+    where
+        K: Key<'a>,
+    {
+        self.out_of_range += rhs.out_of_range;
+        for slot in rhs.slots.into_iter().flatten() {
+            self.add_slot(slot);
+        }
+    }
+    /// Folds the city of `slot` into the one of the same name, or inserts it if there is none
+    fn add_slot<'a>(&mut self, slot: Slot<K>)
+    where
+        K: Key<'a>,
+    {
+        match self.find(slot.name.name(), slot.hash) {
+            Ok(i) => self.slots[i].as_mut().unwrap().city.add_result(slot.city),
+            Err(i) => {
+                slot.city.debug_check();
+                self.insert(i, slot);
+            }
+        }
+    }
+}
+
+/// The text output `{A=min/mean/max, B=...}` sorted by the bytes of the names, skipping cities without values.
+/// The precision of the formatter applies to every city, see [`City`].
+impl<'a, K: Key<'a>> Display for Citymap<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut cities = self
+            .iter()
+            .filter(|(_, city)| !city.is_empty())
+            .collect::<Vec<_>>();
+        cities.sort_unstable_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+        let precision = f.precision().unwrap_or(1);
+        write!(f, "{{")?;
+        for (i, (name, city)) in cities.into_iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            let name = String::from_utf8_lossy(name.name());
+            write!(f, "{name}={city:.precision$}")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Owning iterator over the cities of a [`Citymap`] in no particular order
+pub struct IntoIter<K = String>(std::iter::Flatten<std::vec::IntoIter<Option<Slot<K>>>>);
+
+impl<K> Iterator for IntoIter<K> {
+    type Item = (K, City);
+
+    fn next(&mut self) -> Option<(K, City)> {
+        self.0.next().map(|slot| (slot.name, slot.city))
+    }
+}
+
+impl<K> IntoIterator for Citymap<K> {
+    type Item = (K, City);
+    type IntoIter = IntoIter<K>;
+
+    fn into_iter(self) -> IntoIter<K> {
+        IntoIter(self.slots.into_iter().flatten())
+    }
+}
+
+/// Cities of the same name are combined with [`City::add_result`] rather than replacing each other
+impl<'a, K: Key<'a>> Extend<(K, City)> for Citymap<K> {
+    fn extend<I: IntoIterator<Item = (K, City)>>(&mut self, iter: I) {
+        for (name, city) in iter {
+            self.add_slot(Slot {
+                hash: hash_name(name.name()),
+                name,
+                city,
+            });
+        }
+    }
+}
+
+impl<'a, K: Key<'a>> FromIterator<(K, City)> for Citymap<K> {
+    fn from_iter<I: IntoIterator<Item = (K, City)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl Citymap {
+    /// Aggregates every line of `input` into the cities already in the map, so new data can be added
+    /// to an earlier result (see [`Citymap::load`]) without reading the old data again.
+    /// Lines before an error are kept, error offsets count from the start of `input`.
+    pub fn ingest_reader(&mut self, input: &mut impl BufRead) -> Result<(), ParseError> {
+        // Without a config there is no stop flag anyone could set
+        let stop = AtomicBool::new(false);
+        let mut progress = ProgressBatch::new(None, &stop);
+        citymap_naive(input, 0, None, LineFormat::default(), &mut progress, self)
+    }
+    /// Final statistics ordered by city name
+    pub fn into_stats(self) -> BTreeMap<String, CityStats> {
+        self.slots
+            .into_iter()
+            .flatten()
+            .map(|slot| (slot.name, slot.city.stats()))
+            .collect()
+    }
+}
+
+impl Citymap<&[u8]> {
+    /// Copies every borrowed name into an owned one, once per station.
+    /// Raw names kept by [`Citymap::lookup_lossy`] are converted like [`Key::from_lossy`] does for owned ones.
+    pub fn into_owned(self) -> Citymap {
+        let mut owned = Citymap {
+            out_of_range: self.out_of_range,
+            histogram_resolution: self.histogram_resolution,
+            ..Citymap::with_capacity(self.len)
+        };
+        for slot in self.slots.into_iter().flatten() {
+            // A converted raw name may equal a valid one, so names are not necessarily distinct here
+            match String::from_name(slot.name) {
+                Some(name) => owned.add_slot(Slot {
+                    hash: slot.hash,
+                    name,
+                    city: slot.city,
+                }),
+                None => owned.extend([(String::from_lossy(slot.name), slot.city)]),
+            }
+        }
+        owned
+    }
+}
+
+/// Shared count of bytes parsed so far, see [`aggregate_file`]
+pub type Progress = Arc<AtomicU64>;
+
+/// How much of a multi threaded run one worker did, see [`aggregate_file`]
+#[derive(Copy, Clone, Debug)]
+pub struct WorkerStats {
+    pub thread: usize,
+    /// Bytes of all chunks the worker aggregated
+    pub bytes: u64,
+    /// From the worker starting until it found no chunks left
+    pub elapsed: Duration,
+}
+
+/// Bytes a worker parses before publishing them, keeping the shared counter off the hot path
+const PROGRESS_STEP: u64 = 1 << 20;
+
+/// Batches updates to an optional progress counter, flushing the remainder when dropped.
+/// Carries the stop flag of the run along, which parsing checks whenever a batch is published.
+struct ProgressBatch<'a> {
+    counter: Option<&'a AtomicU64>,
+    stop: &'a AtomicBool,
+    pending: u64,
+}
+
+impl<'a> ProgressBatch<'a> {
+    fn new(counter: Option<&'a AtomicU64>, stop: &'a AtomicBool) -> Self {
+        Self {
+            counter,
+            stop,
+            pending: 0,
+        }
+    }
+    /// Whether the run was asked to stop, see [`AggregateConfig::stop`]
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+    /// Returns whether the batch was just published, which doubles as a cheap periodic tick
+    fn add(&mut self, bytes: u64) -> bool {
+        self.pending += bytes;
+        let full = self.pending >= PROGRESS_STEP;
+        if full {
+            self.flush();
+        }
+        full
+    }
+    fn flush(&mut self) {
+        if let Some(counter) = self.counter {
+            counter.fetch_add(self.pending, Ordering::Relaxed);
+        }
+        self.pending = 0;
+    }
+}
+
+impl Drop for ProgressBatch<'_> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Sets `stop` on the first Ctrl-C instead of terminating, a second one terminates as usual.
+/// Pass the same flag to [`AggregateConfig::stop`] to have aggregations wind down on an interrupt.
+/// Only the flag of the first call is ever set.
+#[cfg(feature = "interrupt")]
+pub fn stop_on_interrupt(stop: Arc<AtomicBool>) {
+    interrupt::install(stop);
+}
+
+/// Byte order mark some files start with, which is not part of the first line
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Separator between city and value unless configured otherwise
+pub const DEFAULT_DELIMITER: u8 = b';';
+
+/// How the lines of an input are laid out
+#[derive(Copy, Clone, Debug)]
+pub struct LineFormat {
+    /// Separates city and value, or all fields of a line with more than two, see [`LineFormat::value_column`]
+    pub delimiter: u8,
+    /// Ends every line, a newline unless records are framed differently, such as NUL separated ones
+    pub terminator: u8,
+    /// Lines starting with this byte are skipped, none by default as station names may start with any byte.
+    /// The command line skips lines starting with `#` unless told otherwise.
+    pub comment: Option<u8>,
+    /// Inclusive bounds in tenths, values outside of them are still aggregated but counted in [`Citymap::out_of_range`]
+    pub valid_range: Option<(i64, i64)>,
+    /// Names that are not valid UTF-8 become stations with invalid sequences replaced by U+FFFD instead of errors.
+    /// They are aggregated under the replaced name, so distinct raw names that are the same after replacement,
+    /// or the same as a valid name, are reported as one merged station.
+    /// Has no effect with the `unsafe` feature, which does not validate names.
+    pub lossy_names: bool,
+    /// Field holding the station name, counting from zero
+    pub key_column: usize,
+    /// Field holding the value, counting from zero. With the default columns a line has exactly those two fields,
+    /// any others split the line on every delimiter and ignore the fields not selected.
+    pub value_column: usize,
+    /// Fraction of lines within `0.0..=1.0` to aggregate, `None` aggregating all of them. Lines are picked by a hash
+    /// of their offset, so the same ones are picked whatever the thread count. The others are not parsed at all,
+    /// errors in them go unnoticed.
+    pub sample: Option<f64>,
+}
+
+impl Default for LineFormat {
+    fn default() -> Self {
+        Self {
+            delimiter: DEFAULT_DELIMITER,
+            terminator: b'\n',
+            comment: None,
+            valid_range: None,
+            lossy_names: false,
+            key_column: 0,
+            value_column: 1,
+            sample: None,
+        }
+    }
+}
+
+/// How [`aggregate_file`] and [`aggregate_reader`] aggregate their input, built up from the defaults:
+///
+/// ```
+/// # use onebrc::{AggregateConfig, LineFormat};
+/// let config = AggregateConfig::new().threads(4).format(LineFormat {
+///     delimiter: b',',
+///     ..LineFormat::default()
+/// });
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AggregateConfig {
+    threads: usize,
+    buffer_bytes: Option<usize>,
+    limit: Option<u64>,
+    format: LineFormat,
+    pin: bool,
+    progress: Option<Progress>,
+    stats: Option<Sender<WorkerStats>>,
+    stop: Arc<AtomicBool>,
+    histogram_resolution: Option<u32>,
+}
+
+impl AggregateConfig {
+    /// One worker per available core, reading whole inputs of the default [`LineFormat`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Worker threads to run, resolving 0 to one per available core
+    fn workers(&self) -> usize {
+        match self.threads {
+            0 => available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+    /// Tenths of a degree per histogram bucket, see [`AggregateConfig::histogram_resolution`]
+    fn resolution(&self) -> u32 {
+        self.histogram_resolution.unwrap_or(1)
+    }
+    /// An empty map for one worker, sized for [`EXPECTED_STATIONS`] and creating cities at the configured resolution
+    fn worker_map<'a, K: Key<'a>>(&self) -> Citymap<K> {
+        Citymap::with_capacity(EXPECTED_STATIONS).with_histogram_resolution(self.resolution())
+    }
+    /// Workers to split files across, 0 (the default) picking one per available core
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+    /// Read buffer size, defaulting to [`SINGLE_THREAD_BUFFER_BYTES`] or [`WORKER_BUFFER_BYTES`]
+    pub fn buffer_bytes(mut self, bytes: usize) -> Self {
+        self.buffer_bytes = Some(bytes);
+        self
+    }
+    /// Aggregates only the lines starting within the first `bytes`, which for compressed files counts decompressed bytes
+    pub fn limit(mut self, bytes: u64) -> Self {
+        self.limit = Some(bytes);
+        self
+    }
+    /// Layout of the lines, blank lines and comments being skipped
+    pub fn format(mut self, format: LineFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// Binds worker `i` to logical CPU `i`, which only has an effect with the `affinity` feature on Linux
+    pub fn pin(mut self, pin: bool) -> Self {
+        self.pin = pin;
+        self
+    }
+    /// Counts values in histogram buckets of `tenths` tenths of a degree instead of one, which only has an effect
+    /// with the `percentiles` feature. Values are only known to a tenth, so the default of one keeps percentiles
+    /// and the mode exact, while coarser buckets take proportionally less memory per city but place them only to
+    /// within a bucket. Maps of different resolutions cannot be merged.
+    pub fn histogram_resolution(mut self, tenths: u32) -> Self {
+        assert!(tenths > 0, "histogram buckets must span at least a tenth");
+        self.histogram_resolution = Some(tenths);
+        self
+    }
+    /// Makes the aggregation stop early once `stop` is set, from any thread or a signal handler, see
+    /// [`stop_on_interrupt`]. Workers then take no further chunks and stop after the line they are on
+    /// within at most about a MiB of input, so the results cover only part of the input.
+    pub fn stop(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+    /// Advances `progress` by the number of bytes parsed as workers go
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+    /// Makes every worker of a multi threaded run send its [`WorkerStats`] to `stats` once it is done
+    pub fn stats(mut self, stats: Sender<WorkerStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+}
+
+/// Aggregates every line of `input` on the current thread.
+/// Of `config` only the format, limit, progress and stop flag apply, `input` being buffered already.
+pub fn aggregate_reader(
+    input: &mut impl BufRead,
+    config: &AggregateConfig,
+) -> Result<Citymap, ParseError> {
+    let mut map = config.worker_map();
+    let mut progress = ProgressBatch::new(config.progress.as_deref(), &config.stop);
+    citymap_naive(
+        input,
+        0,
+        config.limit,
+        config.format,
+        &mut progress,
+        &mut map,
+    )?;
+    Ok(map)
+}
+
+/// Aggregates every reader of `inputs` like [`aggregate_reader`], on up to [`AggregateConfig::threads`] workers that
+/// each take the next reader as they finish one, and merges their results. A parallel fold over inputs that come
+/// split already, such as the shards of a dataset or several decompressed streams, which [`aggregate_file`] would
+/// only take one after another. Of `config` only the threads, format, limit, pin, progress and stop flag apply,
+/// the limit to every reader on its own. Offsets in errors count from the start of the reader the line is in.
+pub fn aggregate_readers<R: BufRead + Send>(
+    inputs: Vec<R>,
+    config: &AggregateConfig,
+) -> Result<Citymap, ParseError> {
+    let workers = config.workers().min(inputs.len());
+    // Every reader is taken out of its slot by the one worker that pops it
+    let readers = inputs.into_iter().map(|r| Mutex::new(Some(r))).collect();
+    let queue = ChunkQueue::new(readers, config.stop.clone());
+    thread::scope(|s| {
+        let workers = (0..workers)
+            .map(|i| {
+                let queue = &queue;
+                s.spawn(move || {
+                    pin_worker(config.pin, i);
+                    let mut map = config.worker_map();
+                    let mut progress = ProgressBatch::new(config.progress.as_deref(), &queue.stop);
+                    while let Some(input) = queue.pop() {
+                        let mut input = input.lock().unwrap().take().expect("popped twice");
+                        let (limit, format) = (config.limit, config.format);
+                        citymap_naive(&mut input, 0, limit, format, &mut progress, &mut map)?;
+                    }
+                    Ok(map)
+                })
+            })
+            .collect::<Vec<_>>();
+        let partials = workers
+            .into_iter()
+            .map(|e| e.join().unwrap_or_else(|panic| resume_unwind(panic)))
+            .collect::<Result<Vec<_>, ParseError>>()?;
+        Ok(merge_tree(partials))
+    })
+}
+
+/// Read buffer of the single threaded path, which streams the whole file through one reader
+pub const SINGLE_THREAD_BUFFER_BYTES: usize = 100_000_000;
+/// Read buffer of each multi threaded worker, allocated once and reused for all of its chunks.
+/// At this size a 265 MB file takes about 250 reads, which from the page cache cost under 5% of the parsing time,
+/// so batching them through io_uring would have little left to save.
+pub const WORKER_BUFFER_BYTES: usize = 1 << 20;
+
+/// Aggregates the file at `path` as `config` describes, splitting it across its workers.
+/// A `path` of `-` reads from stdin.
+/// Compressed files (see [`is_compressed`]) and inputs that are not regular files (see [`is_stream`]) are streamed by a single worker.
+pub fn aggregate_file(path: &str, config: &AggregateConfig) -> Result<Citymap, ParseError> {
+    // Sniffing the format would consume the first bytes of a pipe
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if let Some(program) = (!is_stream(path))
+        .then(|| compressed::decompressor(path))
+        .flatten()
+    {
+        return citymap_decompressed(program, path, config);
+    }
+    // A single worker gains nothing from the channel/merge overhead
+    if config.workers() == 1 || is_stream(path) {
+        citymap_single_thread(path, config)
+    } else {
+        citymap_multi_threaded(path, config)
+    }
+}
+
+/// Aggregates the lines of the byte `range` of the file at `path` on the current thread.
+/// Both ends are aligned like the chunks of a multi threaded run, so adjacent ranges split the lines between them
+/// and processes aggregating the ranges of one file can combine their results, see [`Citymap::write_to`].
+/// Of `config` only the buffer size, format, progress and stop flag apply.
+pub fn aggregate_range(
+    path: &str,
+    range: Range<u64>,
+    config: &AggregateConfig,
+) -> Result<Citymap, ParseError> {
+    let format = config.format;
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let range = align_range(&file, range, size, format.terminator)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    let buffer_bytes = config.buffer_bytes.unwrap_or(SINGLE_THREAD_BUFFER_BYTES);
+    let mut buffered = BufReader::with_capacity(buffer_bytes, file).take(range.end - range.start);
+    let mut map = config.worker_map();
+    let mut progress = ProgressBatch::new(config.progress.as_deref(), &config.stop);
+    citymap_naive(
+        &mut buffered,
+        range.start,
+        None,
+        format,
+        &mut progress,
+        &mut map,
+    )?;
+    Ok(map)
+}
+
+/// Aggregates `path` on the current thread, `-` denoting stdin.
+/// Streaming stdin is never parallelized so throughput is lower, but the results are identical.
+fn citymap_single_thread(path: &str, config: &AggregateConfig) -> Result<Citymap, ParseError> {
+    let buffer_bytes = config.buffer_bytes.unwrap_or(SINGLE_THREAD_BUFFER_BYTES);
+    let (limit, format) = (config.limit, config.format);
+    let mut progress = ProgressBatch::new(config.progress.as_deref(), &config.stop);
+    if path == "-" {
+        let mut map = config.worker_map();
+        let mut buf = BufReader::with_capacity(buffer_bytes, stdin().lock());
+        citymap_naive(&mut buf, 0, limit, format, &mut progress, &mut map)?;
+        return Ok(map);
+    }
+
+    let f = File::open(path)?;
+    let mut buf = BufReader::with_capacity(buffer_bytes, f);
+    let mut map = config.worker_map();
+    citymap_naive(&mut buf, 0, limit, format, &mut progress, &mut map)?;
+    Ok(map)
+}
+
+/// How long [`follow_file`] sleeps at the end of its input before looking for more
+const FOLLOW_POLL: Duration = Duration::from_millis(100);
+
+/// Aggregates the file at `path` on the current thread like [`aggregate_file`], but waits for further lines at
+/// its end instead of finishing, as for a log that is still being appended to. While caught up, `snapshot`
+/// receives the results so far every `interval`, and once the flag of [`AggregateConfig::stop`] is set
+/// the results are returned. A file that is truncated or replaced meanwhile is not noticed.
+/// Of `config` only the buffer size, format, progress and stop flag apply.
+pub fn follow_file(
+    path: &str,
+    config: &AggregateConfig,
+    interval: Duration,
+    mut snapshot: impl FnMut(&Citymap),
+) -> Result<Citymap, ParseError> {
+    let file = File::open(path)?;
+    let buffer_bytes = config.buffer_bytes.unwrap_or(SINGLE_THREAD_BUFFER_BYTES);
+    let mut buf = BufReader::with_capacity(buffer_bytes, file);
+    let mut map = config.worker_map();
+    let mut last_snapshot = Instant::now();
+    let wait = |map: &Citymap| {
+        if last_snapshot.elapsed() >= interval {
+            snapshot(map);
+            last_snapshot = Instant::now();
+        }
+        thread::sleep(FOLLOW_POLL);
+        !config.stop.load(Ordering::Relaxed)
+    };
+    let mut progress = ProgressBatch::new(config.progress.as_deref(), &config.stop);
+    citymap_follow(
+        &mut buf,
+        0,
+        None,
+        config.format,
+        &mut progress,
+        &mut map,
+        wait,
+    )?;
+    Ok(map)
+}
+
+/// Malformed lines reported in full by [`Validation`], later ones are only counted
+pub const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Outcome of checking an input with [`validate_file`] or [`validate_reader`]
+#[derive(Debug, Default)]
+pub struct Validation {
+    /// Lines read, including blank and comment lines
+    pub lines: u64,
+    /// Lines that aggregation would fail on
+    pub malformed: u64,
+    /// The first of the malformed lines with their line number, counting from 1
+    pub errors: Vec<(u64, ParseError)>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.malformed == 0
+    }
+}
+
+/// Checks every line of `input` the way aggregation parses it, but carries on past malformed lines
+/// and aggregates nothing. Only failing to read `input` is an error.
+pub fn validate_reader(input: &mut impl BufRead, format: LineFormat) -> io::Result<Validation> {
+    let mut validation = Validation::default();
+    let mut buf = vec![];
+    let mut offset = 0;
+    loop {
+        let read = input.read_until(format.terminator, &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        validation.lines += 1;
+        let line = buf.strip_suffix(&[format.terminator]).unwrap_or(&buf);
+        let checked = parse_record(line, offset, format).and_then(|record| match record {
+            Some((name, _)) if !format.lossy_names && name_to_str(name).is_none() => {
+                Err(ParseError::InvalidUtf8 {
+                    offset,
+                    line: String::from_utf8_lossy(line).into_owned(),
+                })
+            }
+            _ => Ok(()),
+        });
+        if let Err(e) = checked {
+            validation.malformed += 1;
+            if validation.errors.len() < MAX_REPORTED_ERRORS {
+                validation.errors.push((validation.lines, e));
+            }
+        }
+        offset += read as u64;
+        buf.clear();
+    }
+    Ok(validation)
+}
+
+/// [`validate_reader`] over the file at `path`, reading stdin for `-` and decompressing like [`aggregate_file`].
+/// Of `config` only the buffer size and format apply.
+pub fn validate_file(path: &str, config: &AggregateConfig) -> io::Result<Validation> {
+    let format = config.format;
+    let buffer_bytes = config.buffer_bytes.unwrap_or(SINGLE_THREAD_BUFFER_BYTES);
+    if path == "-" {
+        let mut buf = BufReader::with_capacity(buffer_bytes, stdin().lock());
+        return validate_reader(&mut buf, format);
+    }
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if let Some(program) = (!is_stream(path))
+        .then(|| compressed::decompressor(path))
+        .flatten()
+    {
+        return compressed::read_with(program, path, |stdout| {
+            let mut buf = BufReader::with_capacity(buffer_bytes, stdout);
+            let validation = validate_reader(&mut buf, format);
+            let complete = validation.is_ok();
+            (validation, complete)
+        });
+    }
+    let mut buf = BufReader::with_capacity(buffer_bytes, File::open(path)?);
+    validate_reader(&mut buf, format)
+}
+
+/// Whether `path` is stdin, a pipe or anything else that is not a regular file, which can neither be sized nor seeked
+pub fn is_stream(path: &str) -> bool {
+    path == "-" || !fs::metadata(path).is_ok_and(|m| m.is_file())
+}
+
+/// Whether `path` is in a compressed format enabled through a crate feature.
+/// Progress on those counts decompressed bytes, which exceeds the file size.
+pub fn is_compressed(path: &str) -> bool {
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if !is_stream(path) {
+        return compressed::decompressor(path).is_some();
+    }
+    let _ = path;
+    false
+}
+
+/// Streams the output of the decompressor `program` over `path` through a single worker
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn citymap_decompressed(
+    program: &str,
+    path: &str,
+    config: &AggregateConfig,
+) -> Result<Citymap, ParseError> {
+    let buffer_bytes = config.buffer_bytes.unwrap_or(SINGLE_THREAD_BUFFER_BYTES);
+    let (limit, format) = (config.limit, config.format);
+    compressed::read_with(program, path, |stdout| {
+        let mut buf = BufReader::with_capacity(buffer_bytes, stdout);
+        let mut map = config.worker_map();
+        let mut progress = ProgressBatch::new(config.progress.as_deref(), &config.stop);
+        let result = citymap_naive(&mut buf, 0, limit, format, &mut progress, &mut map);
+        // Parsing stops before the end on errors, at the limit and when interrupted
+        let complete = result.is_ok() && limit.is_none() && !progress.stopped();
+        (result.map(|()| map), complete)
+    })
+}
+
+/// Chunks per worker, so that workers finishing early pick up the remaining work of slower ones
+const CHUNKS_PER_THREAD: usize = 4;
+
+/// Chunks handed out to whichever worker asks next
+struct ChunkQueue<T> {
+    chunks: Vec<T>,
+    next: AtomicUsize,
+    /// The flag of [`AggregateConfig::stop`], shared by every worker taking chunks
+    stop: Arc<AtomicBool>,
+}
+
+impl<T> ChunkQueue<T> {
+    fn new(chunks: Vec<T>, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            chunks,
+            next: AtomicUsize::new(0),
+            stop,
+        }
+    }
+    /// The next chunk, `None` once all are taken or a stop was requested
+    fn pop(&self) -> Option<&T> {
+        if self.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.chunks.get(self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Splits the first `len` bytes of the mapped file into line aligned slices that the workers take turns on
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+fn citymap_mmap(
+    mapping: &Mmap,
+    len: usize,
+    config: &AggregateConfig,
+) -> Result<Citymap, ParseError> {
+    let (cpus, format) = (config.workers(), config.format);
+    let data = &mapping[..len];
+    let chunk_count = (cpus * CHUNKS_PER_THREAD).min(len) as u64;
+    let chunks = aligned_chunks(len as u64, chunk_count, |pos| {
+        Ok(memchr(format.terminator, &data[pos as usize..]).map_or(len as u64, |i| pos + i as u64))
+    })?
+    .into_iter()
+    .map(|range| (&data[range.start as usize..range.end as usize], range.start))
+    .collect();
+    let queue = ChunkQueue::new(chunks, config.stop.clone());
+
+    thread::scope(|s| {
+        let mut threads = vec![];
+        for i in 0..cpus {
+            let queue = &queue;
+            threads.push(
+                thread::Builder::new()
+                    .name(format!("process_thread id: {i}"))
+                    .spawn_scoped(s, move || {
+                        pin_worker(config.pin, i);
+                        let started = Instant::now();
+                        let mut map = config.worker_map();
+                        let progress = config.progress.as_deref();
+                        let bytes = drain_slices(queue, i, mapping, format, progress, &mut map)?;
+                        report_stats(config.stats.as_ref(), i, bytes, started);
+                        // Names borrow from the mapping, which does not outlive this call
+                        Ok(map.into_owned())
+                    })
+                    .unwrap(),
+            );
+        }
+        let partials = threads
+            .into_iter()
+            .map(|e| e.join().unwrap())
+            .collect::<Result<Vec<_>, ParseError>>()?;
+        Ok(merge_tree(partials))
+    })
+}
+
+/// Has worker `i` aggregate slices from `queue` into `map` until none are left, returning the bytes aggregated
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+fn drain_slices<'a>(
+    queue: &ChunkQueue<(&'a [u8], u64)>,
+    i: usize,
+    mapping: &Mmap,
+    format: LineFormat,
+    progress: Option<&AtomicU64>,
+    map: &mut Citymap<&'a [u8]>,
+) -> Result<u64, ParseError> {
+    let mut bytes = 0;
+    let mut progress = ProgressBatch::new(progress, &queue.stop);
+    while let Some(&(chunk, offset)) = queue.pop() {
+        let start = offset as usize;
+        mapping.advise_sequential(start..start + chunk.len());
+        parse_chunk(chunk, offset, format, &mut progress, map)
+            .map_err(|e| e.in_worker(i, offset..offset + chunk.len() as u64))?;
+        bytes += chunk.len() as u64;
+    }
+    Ok(bytes)
+}
+
+/// Sends the [`WorkerStats`] of worker `i` that started at `started`, if anyone asked for them
+fn report_stats(stats: Option<&Sender<WorkerStats>>, i: usize, bytes: u64, started: Instant) {
+    if let Some(stats) = stats {
+        // Nobody listening any more is no reason to fail the run
+        let _ = stats.send(WorkerStats {
+            thread: i,
+            bytes,
+            elapsed: started.elapsed(),
+        });
+    }
+}
+
+fn citymap_multi_threaded(path: &str, config: &AggregateConfig) -> Result<Citymap, ParseError> {
+    let format = config.format;
+    // Opened once and shared, workers only ever read at explicit offsets
+    let file = Arc::new(File::open(path)?);
+    let size = limited_len(file.metadata()?.len(), config.limit, |pos| {
+        next_terminator(&file, pos, format.terminator)
+    })?;
+    // Nothing to split, so don't bother spawning workers over empty ranges
+    if size == 0 {
+        return Ok(Citymap::new().with_histogram_resolution(config.resolution()));
+    }
+
+    // Files that cannot be mapped fall back to the reader based path
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    if let Ok(mapping) = Mmap::map(&file) {
+        return citymap_mmap(&mapping, size as usize, config);
+    }
+
+    // Aligned up front with a few small reads, so workers get ranges of whole lines
+    let cpus = config.workers();
+    let chunk_count = (cpus * CHUNKS_PER_THREAD).min(size as usize) as u64;
+    let chunks = aligned_chunks(size, chunk_count, |pos| {
+        next_terminator(&file, pos, format.terminator)
+    })?;
+    let queue = Arc::new(ChunkQueue::new(chunks, config.stop.clone()));
+
+    // Workers outlive this borrow of the config, so they share a copy of it
+    let config = Arc::new(config.clone());
+    let threads = (0..cpus)
+        .map(|i| citymap_thread(file.clone(), queue.clone(), i, config.clone()))
+        .collect::<Vec<_>>();
+    let partials = threads
+        .into_iter()
+        .map(|e| e.join().unwrap_or_else(|panic| resume_unwind(panic)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(merge_tree(partials))
+}
+
+/// Splits `0..size` into `count` ranges of about equal length, moving every boundary between them onto
+/// the terminator that `line_end` finds at or after it. Adjacent ranges thus split the lines between them
+/// like [`align_range`] does, and ranges that fall within a single line are left out.
+fn aligned_chunks(
+    size: u64,
+    count: u64,
+    mut line_end: impl FnMut(u64) -> io::Result<u64>,
+) -> io::Result<Vec<Range<u64>>> {
+    let per_chunk = size / count;
+    let mut boundaries = vec![0];
+    for i in 1..count {
+        boundaries.push(line_end(i * per_chunk)?.min(size));
+    }
+    boundaries.push(size);
+    Ok(boundaries
+        .windows(2)
+        .map(|bounds| bounds[0]..bounds[1])
+        .filter(|range| !range.is_empty())
+        .collect())
+}
+
+/// Merges disjoint pairs of partial results in parallel until one is left, taking log2(n) rounds.
+/// Relies on merging being associative and commutative, which holds as cities only sum and compare.
+fn merge_tree<'a, K: Key<'a> + Send>(mut maps: Vec<Citymap<K>>) -> Citymap<K> {
+    while maps.len() > 1 {
+        maps = thread::scope(|s| {
+            let mut merges = vec![];
+            let mut unpaired = None;
+            let mut maps = maps.into_iter();
+            while let Some(mut left) = maps.next() {
+                match maps.next() {
+                    Some(right) => merges.push(s.spawn(move || {
+                        left.merge_with(right);
+                        left
+                    })),
+                    None => unpaired = Some(left),
+                }
+            }
+            merges
+                .into_iter()
+                .map(|e| e.join().unwrap())
+                .chain(unpaired)
+                .collect()
+        });
+    }
+    maps.pop().unwrap_or_default()
+}
+
+/// Spawns worker `i`, which aggregates the aligned chunks from `queue` until none are left
+fn citymap_thread(
+    file: Arc<File>,
+    queue: Arc<ChunkQueue<Range<u64>>>,
+    i: usize,
+    config: Arc<AggregateConfig>,
+) -> JoinHandle<Result<Citymap, ParseError>> {
+    thread::Builder::new()
+        .name(format!("process_thread id: {i}"))
+        .spawn(move || {
+            pin_worker(config.pin, i);
+            let started = Instant::now();
+            let mut bytes = 0;
+            let mut map = config.worker_map();
+            let buffer_bytes = config.buffer_bytes.unwrap_or(WORKER_BUFFER_BYTES);
+            let mut progress = ProgressBatch::new(config.progress.as_deref(), &queue.stop);
+            // Chunks are read to their end before the next one is taken, so the drained buffer can move on to it
+            let mut buffered = BufReader::with_capacity(
+                buffer_bytes,
+                RangeReader {
+                    file: &file,
+                    range: 0..0,
+                },
+            );
+            while let Some(range) = queue.pop() {
+                debug_assert!(buffered.buffer().is_empty());
+                buffered.get_mut().range = range.clone();
+                citymap_naive(
+                    &mut buffered,
+                    range.start,
+                    None,
+                    config.format,
+                    &mut progress,
+                    &mut map,
+                )
+                .map_err(|e| e.in_worker(i, range.clone()))?;
+                bytes += range.end - range.start;
+            }
+            report_stats(config.stats.as_ref(), i, bytes, started);
+            Ok(map)
+        })
+        .unwrap()
+}
+
+/// Length of the prefix holding every line that starts within the first `limit` of `size` bytes.
+/// `line_end` finds the terminator ending the line a position falls into.
+fn limited_len(
+    size: u64,
+    limit: Option<u64>,
+    line_end: impl FnOnce(u64) -> io::Result<u64>,
+) -> io::Result<u64> {
+    match limit {
+        Some(0) => Ok(0),
+        Some(limit) if limit < size => line_end(limit - 1),
+        _ => Ok(size),
+    }
+}
+
+/// Moves both ends of `range` onto the `terminator` at or after them, clamped to `size`.
+/// Adjacent ranges therefore stay adjacent, and every line belongs to exactly one of them.
+fn align_range(
+    file: &File,
+    range: Range<u64>,
+    size: u64,
+    terminator: u8,
+) -> io::Result<Range<u64>> {
+    // Skip head alignment for start of file
+    let start = match range.start {
+        0 => 0,
+        start => next_terminator(file, start.min(size), terminator)?,
+    };
+    // tail alignment, the last chunk already ends with the file or the prefix
+    let end = match range.end >= size {
+        true => size,
+        false => next_terminator(file, range.end, terminator)?,
+    };
+    Ok(start..end.max(start))
+}
+
+/// Binds worker `i` to its own core if requested and supported
+fn pin_worker(pin: bool, i: usize) {
+    #[cfg(feature = "affinity")]
+    if pin {
+        affinity::pin_to_core(i);
+    }
+    #[cfg(not(feature = "affinity"))]
+    let _ = (pin, i);
+}
+
+/// Reads from `offset` without relying on the platform specific `FileExt` at the call site
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::FileExt::read_at(file, buf, offset)
+    }
+    // Unlike its unix counterpart this moves the file cursor, which no caller relies on
+    #[cfg(windows)]
+    {
+        std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+    }
+}
+
+/// Reads exactly the bytes of `range` through positional reads, leaving the cursor of a shared file alone
+struct RangeReader<'f> {
+    file: &'f File,
+    range: Range<u64>,
+}
+
+impl Read for RangeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = (buf.len() as u64).min(self.range.end - self.range.start) as usize;
+        let read = read_at(self.file, &mut buf[..len], self.range.start)?;
+        self.range.start += read as u64;
+        Ok(read)
+    }
+}
+
+/// Reads from `offset` until `buf` is full or the file ends, returning the number of bytes read.
+/// A single positional read may legally return less even in the middle of a file.
+fn read_full_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match read_at(file, &mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(len) => filled += len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Returns the offset of the first `terminator` at or after `pos`, or the end of the file if there is none.
+/// Probes in growing windows so that arbitrarily long lines are handled.
+fn next_terminator(file: &File, mut pos: u64, terminator: u8) -> io::Result<u64> {
+    let mut probe = vec![0; 64];
+    loop {
+        let len = read_full_at(file, &mut probe, pos)?;
+        // Reached EOF without encountering a terminator
+        if len == 0 {
+            return Ok(pos);
+        }
+        if let Some(i) = probe[..len].iter().position(|&b| b == terminator) {
+            return Ok(pos + i as u64);
+        }
+        pos += len as u64;
+        probe.resize(probe.len() * 2, 0);
+    }
+}
+
+/// Aggregates all lines of `input` into `map`, `offset` being the position of `input` within the file for error reporting.
+/// Reading stops at the first line starting at or after `limit`.
+fn citymap_naive(
+    input: &mut impl BufRead,
+    offset: u64,
+    limit: Option<u64>,
+    format: LineFormat,
+    progress: &mut ProgressBatch<'_>,
+    map: &mut Citymap,
+) -> Result<(), ParseError> {
+    citymap_follow(input, offset, limit, format, progress, map, |_| false)
+}
+
+/// [`citymap_naive`] for an input that may still grow: on reaching its end, `wait` is called with the results so far
+/// and reading goes on if it returns true. A line that is not terminated yet is kept for when its rest arrives.
+fn citymap_follow(
+    input: &mut impl BufRead,
+    mut offset: u64,
+    limit: Option<u64>,
+    format: LineFormat,
+    progress: &mut ProgressBatch<'_>,
+    map: &mut Citymap,
+    mut wait: impl FnMut(&Citymap) -> bool,
+) -> Result<(), ParseError> {
+    // A line cut off by the end of the buffer, and the offset it starts at
+    let mut split = vec![];
+    let mut split_offset = 0;
+    loop {
+        if split.is_empty() && limit.is_some_and(|limit| offset >= limit) {
+            break;
+        }
+        let buf = input.fill_buf()?;
+        // Stream has finished, its final line may lack a trailing terminator
+        if buf.is_empty() {
+            if wait(map) {
+                continue;
+            }
+            // A line still being written when following is stopped is dropped rather than reported as malformed
+            if !progress.stopped() {
+                parse_chunk(&split, split_offset, format, progress, map)?;
+            }
+            break;
+        }
+
+        let read = if !split.is_empty() {
+            let end = memchr(format.terminator, buf).map_or(buf.len(), |i| i + 1);
+            split.extend_from_slice(&buf[..end]);
+            if split.last() == Some(&format.terminator) {
+                parse_chunk(&split, split_offset, format, progress, map)?;
+                split.clear();
+            }
+            end
+        } else {
+            // Lines in full are parsed in place, the rest is left in the buffer for the next round
+            let mut end = buf
+                .iter()
+                .rposition(|&b| b == format.terminator)
+                .map_or(0, |i| i + 1);
+            if let Some(limit) = limit.filter(|&limit| offset + end as u64 > limit) {
+                let last = (limit - offset - 1) as usize;
+                end = last + memchr(format.terminator, &buf[last..end]).unwrap() + 1;
+            }
+            if end == 0 {
+                split.extend_from_slice(buf);
+                split_offset = offset;
+                buf.len()
+            } else {
+                parse_chunk(&buf[..end], offset, format, progress, map)?;
+                end
+            }
+        };
+        input.consume(read);
+        offset += read as u64;
+        if progress.stopped() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Aggregates all lines of an in-memory chunk into `map`, `offset` being the position of `chunk` within the file
+fn parse_chunk<'a, K: Key<'a>>(
+    chunk: &'a [u8],
+    mut offset: u64,
+    format: LineFormat,
+    progress: &mut ProgressBatch<'_>,
+    map: &mut Citymap<K>,
+) -> Result<(), ParseError> {
+    let mut rest = chunk;
+    while !rest.is_empty() {
+        let end = memchr(format.terminator, rest).unwrap_or(rest.len());
+        aggregate_line(map, &rest[..end], offset, format)?;
+        rest = rest.get(end + 1..).unwrap_or_default();
+        offset += end as u64 + 1;
+        if progress.add(end as u64 + 1) && progress.stopped() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Splits a single line excluding its newline into the city name and its value in tenths.
+/// Errors report an offset of 0, as the position of the line within a file is not known here.
+pub fn parse_line(line: &[u8]) -> Result<(&[u8], i64), ParseError> {
+    split_line(line, 0, DEFAULT_DELIMITER)
+}
+
+/// [`parse_line`] with the position of the line for error reporting and a configurable delimiter
+fn split_line(line: &[u8], line_offset: u64, delimiter: u8) -> Result<(&[u8], i64), ParseError> {
+    let line_str = || String::from_utf8_lossy(line).into_owned();
+    let Some(i) = memchr(delimiter, line) else {
+        return Err(ParseError::MissingSeparator {
+            offset: line_offset,
+            line: line_str(),
+        });
+    };
+    let city = &line[0..i];
+    let val = &line[(i + 1)..];
+
+    let val = parse_value(val).map_err(|e| {
+        // A second separator can never parse as a value, so only failed lines need to be checked for one
+        if memchr(delimiter, val).is_some() {
+            return ParseError::ExtraSeparator {
+                offset: line_offset,
+                line: line_str(),
+            };
+        }
+        value_error(e, line, line_offset)
+    })?;
+    Ok((city, val))
+}
+
+/// [`split_line`] for lines of more than two fields, picking the columns `format` selects
+fn split_columns(
+    line: &[u8],
+    line_offset: u64,
+    format: LineFormat,
+) -> Result<(&[u8], i64), ParseError> {
+    let (mut city, mut val) = (None, None);
+    let last = format.key_column.max(format.value_column);
+    for (i, field) in line
+        .split(|&b| b == format.delimiter)
+        .take(last + 1)
+        .enumerate()
+    {
+        if i == format.key_column {
+            city = Some(field);
+        }
+        if i == format.value_column {
+            val = Some(field);
+        }
+    }
+    let (Some(city), Some(val)) = (city, val) else {
+        return Err(ParseError::MissingSeparator {
+            offset: line_offset,
+            line: String::from_utf8_lossy(line).into_owned(),
+        });
+    };
+    let val = parse_value(val).map_err(|e| value_error(e, line, line_offset))?;
+    Ok((city, val))
+}
+
+/// Places a failure to parse the value of `line` in it
+fn value_error(e: ValueError, line: &[u8], line_offset: u64) -> ParseError {
+    let line = String::from_utf8_lossy(line).into_owned();
+    let offset = line_offset;
+    match e {
+        ValueError::BadDigit(byte) => ParseError::BadDigit { offset, line, byte },
+        ValueError::Empty => ParseError::EmptyValue { offset, line },
+        ValueError::OutOfRange => ParseError::OutOfRange { offset, line },
+    }
+}
+
+/// Splits a single line excluding its terminator into the city name and its value, `None` for blank and comment lines
+fn parse_record(
+    mut line: &[u8],
+    line_offset: u64,
+    format: LineFormat,
+) -> Result<Option<(&[u8], i64)>, ParseError> {
+    // Editors on Windows like to start files with a byte order mark, which would end up in the first name
+    if line_offset == 0 {
+        line = line.strip_prefix(UTF8_BOM).unwrap_or(line);
+    }
+    // Blank lines also cover the lone terminators that chunk alignment leaves behind
+    if line.trim_ascii_end().is_empty() || line.first() == format.comment.as_ref() {
+        return Ok(None);
+    }
+    match (format.key_column, format.value_column) {
+        (0, 1) => split_line(line, line_offset, format.delimiter),
+        _ => split_columns(line, line_offset, format),
+    }
+    .map(Some)
+}
+
+/// Parses a single line excluding its terminator and adds its value to the matching city
+fn aggregate_line<'a, K: Key<'a>>(
+    map: &mut Citymap<K>,
+    line: &'a [u8],
+    line_offset: u64,
+    format: LineFormat,
+) -> Result<(), ParseError> {
+    if format
+        .sample
+        .is_some_and(|rate| !sampled(line_offset, rate))
+    {
+        return Ok(());
+    }
+    let Some((city, val)) = parse_record(line, line_offset, format)? else {
+        return Ok(());
+    };
+    if let Some((min, max)) = format.valid_range {
+        map.out_of_range += !(min..=max).contains(&val) as u64;
+    }
+    let city = match format.lossy_names {
+        true => map.lookup_lossy(city),
+        false => map.lookup(city).ok_or_else(|| ParseError::InvalidUtf8 {
+            offset: line_offset,
+            line: String::from_utf8_lossy(line).into_owned(),
+        })?,
+    };
+    city.add_new_value(val);
+    Ok(())
+}
+
+/// Whether the line at `offset` is among the `rate` of lines [`LineFormat::sample`] keeps.
+/// Hashing the content instead would keep or drop all repeats of a line together, sampling values rather than rows.
+fn sampled(offset: u64, rate: f64) -> bool {
+    // The splitmix64 finalizer, which spreads consecutive offsets over the whole range
+    let mut z = offset.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    ((z >> 11) as f64 / (1u64 << 53) as f64) < rate
+}
+
+/// Index of the first `needle` in `haystack`, checking eight bytes at a time.
+/// Only public for the benchmarks comparing it to a byte by byte search.
+#[doc(hidden)]
+pub fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    const LO: u64 = u64::from_ne_bytes([0x01; 8]);
+    const HI: u64 = u64::from_ne_bytes([0x80; 8]);
+    let pattern = LO * needle as u64;
+
+    let mut chunks = haystack.chunks_exact(8);
+    let mut offset = 0;
+    for chunk in &mut chunks {
+        // Bytes equal to the needle become zero, the lowest flagged byte is always a true match
+        let word = u64::from_le_bytes(chunk.try_into().unwrap()) ^ pattern;
+        let found = word.wrapping_sub(LO) & !word & HI;
+        if found != 0 {
+            return Some(offset + (found.trailing_zeros() / 8) as usize);
+        }
+        offset += 8;
+    }
+    chunks
+        .remainder()
+        .iter()
+        .position(|&b| b == needle)
+        .map(|i| offset + i)
+}
@@ -1,286 +1,1057 @@
-#![feature(hash_raw_entry)]
-
-use std::collections::HashMap;
 use std::env::args;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
-use std::ops::{Neg, Range};
-use std::os::unix::fs::FileExt;
-use std::sync::mpsc::{channel, Sender};
-use std::thread;
-use std::thread::{available_parallelism, JoinHandle};
-use std::time::Instant;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::path::Path;
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Copy, Clone, Debug)]
-struct City {
-    min: i64,
-    max: i64,
-    sum: i64,
-    occurrences: u32,
+use onebrc::generate::generate;
+use onebrc::{
+    aggregate_file, aggregate_range, follow_file, is_compressed, is_stream, validate_file,
+    AggregateConfig, City, CityStats, Citymap, LineFormat, Progress, Unit, WorkerStats,
+};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// Between the snapshots of --follow unless given
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+const USAGE: &str = "\
+usage: onebrc [OPTIONS] [INPUT]...
+       onebrc --range START:END [OPTIONS] INPUT
+       onebrc --merge [OPTIONS] [PARTIAL]...
+       onebrc --follow [SECONDS] [OPTIONS] INPUT
+       onebrc generate --rows N [--out PATH] [--seed S]
+
+Aggregates min/mean/max per station of every INPUT (measurements.txt if none,
+`-` for stdin) and prints them sorted by station name. With the interrupt
+feature, Ctrl-C stops early and prints what was aggregated so far.
+
+options:
+  st, --single-thread     aggregate on the calling thread only
+  --threads N             worker threads, 0 picks one per core (default 0)
+  --buffer-bytes N        read buffer size of every worker
+  --limit BYTES           only aggregate the lines starting within the first
+                          BYTES of every input
+  --delimiter BYTE        separator between station and value (default ;)
+  --key-column N          field of the station name, counting from 1 (default 1)
+  --value-column N        field of the value, counting from 1 (default 2), lines
+                          selecting other fields than the first two may have
+                          any number of further ones
+  --terminator BYTE       end of every line (default \\n), such as \\0 for NUL
+                          separated records
+  --comment BYTE          skip lines starting with BYTE (default #)
+  --validate-range [MIN:MAX]
+                          count values outside MIN..=MAX (default -99.9:99.9)
+  --lossy-names           replace invalid UTF-8 in station names with U+FFFD
+                          instead of failing, merging names that end up equal
+  --sample RATE           aggregate only about RATE (e.g. 0.01) of the lines,
+                          picked deterministically, for approximate results
+  --pin                   pin every worker to its own core (affinity feature)
+  --progress              report progress on stderr
+  --quiet                 do not report the elapsed time and throughput on
+                          stderr
+  --stats                 report bytes, time and throughput per worker thread
+                          of multi threaded runs on stderr
+  --output PATH           write the results to PATH instead of stdout
+  --format FORMAT         text, json, csv or msgpack (default text)
+  --precision N           decimals of every statistic (default 1)
+  --unit UNIT             celsius or fahrenheit (default celsius)
+  --stddev                append the standard deviation
+  --percentiles           append p50/p90/p99 (percentiles feature)
+  --mode                  append the most frequent value (percentiles feature)
+  --percentile-resolution DEGREES
+                          width of the histogram buckets behind --percentiles
+                          and --mode, coarser ones taking less memory per
+                          station but being exact only to within a bucket
+                          (default 0.1, which is exact)
+  --top N                 print only the N stations ranking highest --by
+  --bottom N              print only the N stations ranking lowest --by,
+                          lowest first
+  --by STAT               min, mean or max to rank --top or --bottom by
+                          (default mean)
+  --range START:END       aggregate the lines of a byte range of INPUT and
+                          write them as a partial result instead of printing
+  --merge                 combine and print partial results written by --range,
+                          `-` reading any number of them from stdin
+  --follow [SECONDS]      keep reading lines appended to INPUT, printing the
+                          results so far on stderr every SECONDS (default 10)
+                          while caught up; Ctrl-C prints the final results if
+                          built with the interrupt feature
+  --validate              only check that every line of every INPUT parses,
+                          exiting with 1 if any does not
+  -h, --help              print this help
+  -V, --version           print the version";
+
+/// Everything the aggregation command line selects
+struct Args {
+    inputs: Vec<String>,
+    /// Worker threads, 0 picks one per core and 1 aggregates on the calling thread
+    threads: usize,
+    buffer_bytes: Option<usize>,
+    /// Bytes of every input to aggregate, rounded up to whole lines
+    limit: Option<u64>,
+    /// Byte range of the single input to write a partial result for
+    range: Option<Range<u64>>,
+    /// Inputs are partial results to combine
+    merge: bool,
+    /// Only check the inputs for malformed lines
+    validate: bool,
+    /// Keep reading the single input as it grows, with the time between snapshots
+    follow: Option<Duration>,
+    format: LineFormat,
+    pin: bool,
+    progress: bool,
+    /// Leave out the elapsed time and throughput
+    quiet: bool,
+    /// Report how much every worker did
+    stats: bool,
+    output: Option<String>,
+    options: PrintOptions,
+    /// Tenths of a degree per histogram bucket
+    histogram_resolution: Option<u32>,
 }
 
-impl City {
-    pub fn add_new(&mut self, input: &[u8]) {
-        let mut val = 0;
-        let mut is_neg = false;
-        for &char in input {
-            match char {
-                b'0'..=b'9' => {
-                    val *= 10;
-                    let digit = char - b'0';
-                    val += digit as i64;
+impl Args {
+    /// Parses the arguments after the program name, exiting with a message on invalid input
+    fn parse(mut args: Peekable<impl Iterator<Item = String>>) -> Self {
+        let mut parsed = Self {
+            inputs: vec![],
+            threads: 0,
+            buffer_bytes: None,
+            limit: None,
+            range: None,
+            merge: false,
+            validate: false,
+            follow: None,
+            format: LineFormat {
+                comment: Some(b'#'),
+                ..LineFormat::default()
+            },
+            pin: false,
+            progress: false,
+            quiet: false,
+            stats: false,
+            output: None,
+            options: PrintOptions::default(),
+            histogram_resolution: None,
+        };
+        let mut single_thread = false;
+        let mut by = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    exit(0);
+                }
+                "-V" | "--version" => {
+                    println!("onebrc {}", env!("CARGO_PKG_VERSION"));
+                    exit(0);
+                }
+                // The bare `st` predates the flags and is kept for existing scripts
+                "st" | "--single-thread" => single_thread = true,
+                "--threads" => {
+                    parsed.threads = match args.next().map(|n| n.parse::<usize>()) {
+                        Some(Ok(n)) => n,
+                        _ => fail("--threads expects a non-negative integer"),
+                    };
+                }
+                "--buffer-bytes" => {
+                    parsed.buffer_bytes = match args.next().map(|n| n.parse::<usize>()) {
+                        Some(Ok(n)) if n > 0 => Some(n),
+                        _ => fail("--buffer-bytes expects a positive integer"),
+                    };
+                }
+                "--limit" => {
+                    parsed.limit = match args.next().map(|n| n.parse::<u64>()) {
+                        Some(Ok(n)) => Some(n),
+                        _ => fail("--limit expects a non-negative integer"),
+                    };
+                }
+                "--range" => {
+                    parsed.range = match args.next().as_deref().and_then(parse_byte_range) {
+                        Some(range) => Some(range),
+                        None => fail("--range expects START:END byte offsets with START <= END"),
+                    };
+                }
+                "--merge" => parsed.merge = true,
+                "--output" => {
+                    parsed.output = match args.next() {
+                        Some(path) => Some(path),
+                        None => fail("--output expects a path"),
+                    };
+                }
+                "--delimiter" => {
+                    parsed.format.delimiter = match args.next().as_deref().and_then(parse_byte) {
+                        Some(byte) => byte,
+                        None => fail("--delimiter expects a single byte"),
+                    };
+                }
+                "--key-column" => {
+                    parsed.format.key_column = parse_column("--key-column", args.next())
+                }
+                "--value-column" => {
+                    parsed.format.value_column = parse_column("--value-column", args.next());
+                }
+                "--terminator" => {
+                    parsed.format.terminator = match args.next().as_deref().and_then(parse_byte) {
+                        Some(byte) => byte,
+                        None => fail("--terminator expects a single byte"),
+                    };
+                }
+                "--pin" => {
+                    if cfg!(not(feature = "affinity")) {
+                        fail("--pin requires building with the affinity feature");
+                    }
+                    parsed.pin = true;
+                }
+                "--comment" => {
+                    parsed.format.comment = match args.next().as_deref().and_then(parse_byte) {
+                        Some(byte) => Some(byte),
+                        None => fail("--comment expects a single byte"),
+                    };
+                }
+                "--validate-range" => {
+                    // The bounds are optional, defaulting to the range the challenge allows
+                    let bounds = args.next_if(|bounds| parse_range(bounds).is_some());
+                    parsed.format.valid_range =
+                        Some(bounds.map_or((-999, 999), |b| parse_range(&b).unwrap()));
+                }
+                "--validate" => parsed.validate = true,
+                "--follow" => {
+                    let seconds =
+                        |s: &str| s.parse::<f64>().ok().filter(|&s| s > 0.0 && s.is_finite());
+                    let interval = args.next_if(|s| seconds(s).is_some());
+                    parsed.follow = Some(interval.map_or(SNAPSHOT_INTERVAL, |s| {
+                        Duration::from_secs_f64(seconds(&s).unwrap())
+                    }));
+                }
+                "--lossy-names" => parsed.format.lossy_names = true,
+                "--sample" => {
+                    parsed.format.sample = match args.next().map(|rate| rate.parse::<f64>()) {
+                        Some(Ok(rate)) if rate > 0.0 && rate <= 1.0 => Some(rate),
+                        _ => fail("--sample expects a fraction within 0 (exclusive) and 1"),
+                    };
+                }
+                "--progress" => parsed.progress = true,
+                "--quiet" => parsed.quiet = true,
+                "--stats" => parsed.stats = true,
+                "--format" => {
+                    parsed.options.format = match args.next().as_deref() {
+                        Some("text") => OutputFormat::Text,
+                        Some("json") => OutputFormat::Json,
+                        Some("csv") => OutputFormat::Csv,
+                        Some("msgpack") => OutputFormat::Msgpack,
+                        _ => fail("--format expects one of: text, json, csv, msgpack"),
+                    };
                 }
-                b'-' => {
-                    is_neg = true;
+                "--precision" => {
+                    parsed.options.precision = match args.next().map(|n| n.parse()) {
+                        Some(Ok(n)) => n,
+                        _ => fail("--precision expects a non-negative integer"),
+                    };
                 }
-                b'.' => {}
-                _ => {
-                    panic!("encountered {} in value", char::from(char))
+                "--unit" => {
+                    parsed.options.unit = match args.next().as_deref() {
+                        Some("celsius") => Unit::Celsius,
+                        Some("fahrenheit") => Unit::Fahrenheit,
+                        _ => fail("--unit expects celsius or fahrenheit"),
+                    };
                 }
+                "--top" | "--bottom" => {
+                    if parsed.options.top.is_some() {
+                        fail("--top and --bottom can only be given once, and not both");
+                    }
+                    parsed.options.top = match args.next().map(|n| n.parse()) {
+                        Some(Ok(n)) => Some(n),
+                        _ => fail(&format!("{arg} expects a non-negative integer")),
+                    };
+                    parsed.options.bottom = arg == "--bottom";
+                }
+                "--by" => {
+                    by = Some(match args.next().as_deref() {
+                        Some("min") => Rank::Min,
+                        Some("mean") => Rank::Mean,
+                        Some("max") => Rank::Max,
+                        _ => fail("--by expects one of: min, mean, max"),
+                    });
+                }
+                "--stddev" => parsed.options.stddev = true,
+                "--percentiles" => {
+                    if cfg!(not(feature = "percentiles")) {
+                        fail("--percentiles requires building with the percentiles feature");
+                    }
+                    parsed.options.percentiles = true;
+                }
+                "--mode" => {
+                    if cfg!(not(feature = "percentiles")) {
+                        fail("--mode requires building with the percentiles feature");
+                    }
+                    parsed.options.mode = true;
+                }
+                "--percentile-resolution" => {
+                    if cfg!(not(feature = "percentiles")) {
+                        fail("--percentile-resolution requires building with the percentiles feature");
+                    }
+                    // Values are only known to a tenth, finer buckets could not be any more exact
+                    let tenths = args
+                        .next()
+                        .and_then(|d| d.parse::<f64>().ok())
+                        .map(|d| d * 10.0);
+                    parsed.histogram_resolution = match tenths {
+                        Some(t)
+                            if t.round() >= 1.0
+                                && t.round() <= u32::MAX as f64
+                                && (t - t.round()).abs() < 1e-6 =>
+                        {
+                            Some(t.round() as u32)
+                        }
+                        _ => fail("--percentile-resolution expects a positive multiple of 0.1"),
+                    };
+                }
+                // A lone `-` is stdin, anything else with a dash is most likely a typo
+                flag if flag.starts_with('-') && flag != "-" => {
+                    fail(&format!("unknown option {flag:?}, see --help"))
+                }
+                _ => parsed.inputs.push(arg),
             }
         }
-        if is_neg {
-            val = val.neg();
+        if single_thread {
+            parsed.threads = 1;
+        }
+        let terminator = parsed.format.terminator;
+        if parsed.format.delimiter == terminator || parsed.format.comment == Some(terminator) {
+            fail("--delimiter and --comment must differ from the line terminator");
         }
-        self.add_new_value(val);
+        if let Some(by) = by {
+            if parsed.options.top.is_none() {
+                fail("--by requires --top or --bottom");
+            }
+            parsed.options.by = by;
+        }
+        if parsed.format.key_column == parsed.format.value_column {
+            fail("--key-column and --value-column must differ");
+        }
+        if parsed.inputs.is_empty() {
+            parsed.inputs.push("measurements.txt".to_owned());
+        }
+        if parsed.validate && (parsed.range.is_some() || parsed.merge) {
+            fail("--validate cannot be combined with --range or --merge");
+        }
+        if parsed.follow.is_some() {
+            if parsed.range.is_some() || parsed.merge || parsed.validate {
+                fail("--follow cannot be combined with --range, --merge or --validate");
+            }
+            if parsed.limit.is_some() {
+                fail("--follow cannot be combined with --limit");
+            }
+            // Pipes and decompressors already wait for their writer, their end is final
+            match &parsed.inputs[..] {
+                [input] if !is_stream(input) && !is_compressed(input) => {}
+                _ => fail("--follow expects a single regular, uncompressed input"),
+            }
+        }
+        if parsed.range.is_some() {
+            if parsed.merge {
+                fail("--range and --merge cannot be combined");
+            }
+            // Ranges are positions in the file itself
+            match &parsed.inputs[..] {
+                [input] if !is_stream(input) && !is_compressed(input) => {}
+                _ => fail("--range expects a single regular, uncompressed input"),
+            }
+        }
+        parsed
     }
+}
+
+/// Reports a command line error and exits
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}");
+    exit(1);
+}
 
-    pub fn add_new_value(&mut self, new: i64) {
-        self.min = self.min.min(new);
-        self.max = self.max.max(new);
-        self.sum += new;
-        self.occurrences += 1;
+fn main() {
+    let mut args = args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "generate") {
+        args.next();
+        run_generate(args);
+        return;
     }
-    pub fn min(&self) -> f64 {
-        self.min as f64 / 10.0
+    let Args {
+        inputs,
+        threads,
+        buffer_bytes,
+        limit,
+        range,
+        merge,
+        validate,
+        follow,
+        format,
+        pin,
+        progress: show_progress,
+        quiet,
+        stats,
+        output,
+        options,
+        histogram_resolution,
+    } = Args::parse(args);
+    // Only ever set with the interrupt feature
+    let interrupted = Arc::new(AtomicBool::new(false));
+    #[cfg(feature = "interrupt")]
+    onebrc::stop_on_interrupt(interrupted.clone());
+
+    // `-` reads from stdin
+    for input in &inputs {
+        if input != "-" && (!Path::new(input).exists() || Path::new(input).is_dir()) {
+            eprintln!("error: input {input:?} does not exist or is a directory");
+            exit(1);
+        }
     }
-    pub fn mean(&self) -> f64 {
-        self.sum as f64 / self.occurrences as f64 / 10.0
+
+    let mut config = AggregateConfig::new()
+        .threads(threads)
+        .format(format)
+        .pin(pin)
+        .stop(interrupted.clone());
+    if let Some(bytes) = buffer_bytes {
+        config = config.buffer_bytes(bytes);
     }
-    pub fn max(&self) -> f64 {
-        self.max as f64 / 10.0
+    if let Some(limit) = limit {
+        config = config.limit(limit);
     }
-
-    pub fn add_result(&mut self, other: Self) {
-        self.min = self.min.min(other.min);
-        self.max = self.max.max(other.max);
-        self.sum += other.sum;
-        self.occurrences += other.occurrences;
+    if let Some(tenths) = histogram_resolution {
+        config = config.histogram_resolution(tenths);
     }
-}
 
-impl Default for City {
-    fn default() -> Self {
-        Self {
-            min: i64::MAX,
-            max: i64::MIN,
-            sum: 0,
-            occurrences: 0,
+    // Created up front so a bad path fails before the aggregation work
+    let mut out: BufWriter<Box<dyn Write>> = BufWriter::new(match &output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("error: failed to create {path:?}: {e}");
+                exit(1);
+            }
+        },
+        None => Box::new(io::stdout().lock()),
+    });
+
+    // A gate for pipelines, which reports every input before failing on any of them
+    if validate {
+        let mut valid = true;
+        for input in &inputs {
+            let validation = match validate_file(input, &config) {
+                Ok(validation) => validation,
+                Err(e) => {
+                    eprintln!("error: {input}: {e}");
+                    valid = false;
+                    continue;
+                }
+            };
+            for (line, e) in &validation.errors {
+                eprintln!("error: {input}: line {line}: {e}");
+            }
+            let unreported = validation.malformed - validation.errors.len() as u64;
+            if unreported > 0 {
+                eprintln!("error: {input}: {unreported} more malformed lines");
+            }
+            let summary = writeln!(
+                out,
+                "{input}: {} of {} lines malformed",
+                validation.malformed, validation.lines
+            );
+            if let Err(e) = summary {
+                fail(&format!("failed to write results: {e}"));
+            }
+            valid &= validation.is_valid();
         }
+        if let Err(e) = out.flush() {
+            fail(&format!("failed to write results: {e}"));
+        }
+        exit(!valid as i32);
     }
-}
 
-#[derive(Default, Clone, Debug)]
-struct Citymap {
-    // Length then values
-    pub map: HashMap<u32, (String, City)>,
-}
+    // One shard of a distributed run, combined by another process through --merge
+    if let Some(range) = range {
+        let input = &inputs[0];
+        let partial = aggregate_range(input, range, &config)
+            .unwrap_or_else(|e| fail(&format!("{input}: {e}")));
+        // A merge could not tell an incomplete shard from a complete one
+        if interrupted.load(Ordering::Relaxed) {
+            fail("interrupted, no partial result written");
+        }
+        if let Err(e) = partial.write_to(&mut out).and_then(|_| out.flush()) {
+            fail(&format!("failed to write partial result: {e}"));
+        }
+        return;
+    }
 
-fn hashstr(s: &str) -> u32 {
-    let b = s.as_bytes();
-    u32::from_le_bytes([s.len() as u8, b[0], b[1], b[2]])
-}
+    let start = Instant::now();
 
-impl Citymap {
-    pub fn lookup(&mut self, lookup: &str) -> &mut City {
-        let hash = hashstr(lookup);
-        let builder = self.map.raw_entry_mut();
-        &mut builder.from_key(&hash).or_insert(hash, (lookup.to_owned(), Default::default())).1.1
+    // Partial results are small, there is nothing worth reporting progress or throughput on.
+    // The bytes parsed also cover streams and decompressed data, whose size is not known up front.
+    let progress = (!merge && (show_progress || !quiet)).then(Progress::default);
+    let monitor = progress.as_ref().filter(|_| show_progress).map(|progress| {
+        // Streams and compressed files have no known size, so only the byte count can be shown
+        let total = inputs
+            .iter()
+            .map(|input| {
+                (!is_stream(input) && !is_compressed(input)).then(|| {
+                    fs::metadata(input)
+                        .unwrap()
+                        .len()
+                        .min(limit.unwrap_or(u64::MAX))
+                })
+            })
+            .sum();
+        spawn_progress_monitor(progress.clone(), total)
+    });
+    let (worker_stats, worker_receiver) = channel();
+    if let Some(progress) = &progress {
+        config = config.progress(progress.clone());
     }
-    pub fn new() -> Self {
-        Self {
-            map: Default::default(),
+    if stats {
+        config = config.stats(worker_stats);
+    }
+    // Every file is split across the workers on its own, then folded into one result set
+    let results = inputs
+        .iter()
+        .try_fold(Citymap::new(), |mut results, input| {
+            if interrupted.load(Ordering::Relaxed) {
+                return Ok(results);
+            }
+            if let Some(interval) = follow {
+                let partial = follow_file(input, &config, interval, |map| {
+                    // Snapshots go where the elapsed time goes, stdout only gets the final results
+                    let mut err = io::stderr().lock();
+                    if let Err(e) = print_results(&mut err, map.clone(), options) {
+                        eprintln!("error: failed to write snapshot: {e}");
+                    }
+                })
+                .map_err(|e| format!("{input}: {e}"))?;
+                results.merge_with(partial);
+                return Ok(results);
+            }
+            if merge {
+                for partial in read_partials(input).map_err(|e| format!("{input}: {e}"))? {
+                    // Cities of different resolutions cannot be merged
+                    let expected = histogram_resolution.unwrap_or(1);
+                    if partial.histogram_resolution() != expected {
+                        return Err(format!(
+                            "{input}: partial result has histogram buckets of {} tenths instead of {expected}, \
+                             see --percentile-resolution",
+                            partial.histogram_resolution()
+                        ));
+                    }
+                    results.merge_with(partial);
+                }
+                return Ok(results);
+            }
+            let partial = aggregate_file(input, &config).map_err(|e| format!("{input}: {e}"))?;
+            print_worker_stats(input, worker_receiver.try_iter().collect());
+            results.merge_with(partial);
+            Ok::<_, String>(results)
+        });
+    if let Some((stop, monitor)) = monitor {
+        drop(stop);
+        monitor.join().unwrap();
+    }
+
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("error: {e}");
+            exit(1);
         }
+    };
+
+    // Makes rows lost or double counted at chunk boundaries, and stations collapsed by collisions, easy to spot
+    let rows = results.rows();
+    eprintln!("processed {rows} rows of {} stations", results.len());
+    if let Some(rate) = format.sample {
+        eprintln!(
+            "results cover a sample of about {}% of the rows",
+            rate * 100.0
+        );
     }
-    pub fn into_key_values(self) -> Vec<(String, City)> {
-        self.map.into_iter().map(|(_, s)| s).collect()
+    // Following only ever ends through an interrupt
+    if interrupted.load(Ordering::Relaxed) && follow.is_none() {
+        eprintln!("interrupted, the results cover only part of the input");
     }
-    pub fn merge_with(&mut self, rhs: Self) {
-        for (k, v) in rhs.map.into_iter() {
-            self.map
-                .entry(k)
-                .and_modify(|lhs| {
-                    lhs.1.add_result(v.1);
-                })
-                .or_insert(v);
+    if let Some((min, max)) = format.valid_range {
+        eprintln!(
+            "{} values outside of {:.1}..={:.1}",
+            results.out_of_range(),
+            min as f64 / 10.0,
+            max as f64 / 10.0
+        );
+    }
+
+    if let Err(e) = print_results(&mut out, results, options).and_then(|_| out.flush()) {
+        eprintln!("error: failed to write results: {e}");
+        exit(1);
+    }
+
+    // Kept off stdout, which only ever holds the results
+    if !quiet {
+        let elapsed = start.elapsed();
+        match progress {
+            Some(bytes) => {
+                let secs = elapsed.as_secs_f64();
+                eprintln!(
+                    "{elapsed:?}, {:.0} MB/s, {:.0} rows/s",
+                    bytes.load(Ordering::Relaxed) as f64 / 1e6 / secs,
+                    rows as f64 / secs
+                );
+            }
+            None => eprintln!("{elapsed:?}"),
         }
     }
 }
 
-fn main() {
-    let mut args = args();
+/// Reports how much each worker did on `input` on stderr, showing how evenly the work was spread
+fn print_worker_stats(input: &str, mut stats: Vec<WorkerStats>) {
+    stats.sort_unstable_by_key(|stats| stats.thread);
+    for WorkerStats {
+        thread,
+        bytes,
+        elapsed,
+    } in stats
+    {
+        let mb = bytes as f64 / 1e6;
+        let secs = elapsed.as_secs_f64();
+        eprintln!(
+            "{input}: thread {thread}: {mb:.1} MB in {secs:.3}s, {:.0} MB/s",
+            mb / secs
+        );
+    }
+}
 
-    let start = Instant::now();
-    let input = "measurements.txt";
+/// A single byte argument, or one of the escapes `\t`, `\n` and `\0`
+fn parse_byte(arg: &str) -> Option<u8> {
+    match arg.as_bytes() {
+        b"\\t" => Some(b'\t'),
+        b"\\n" => Some(b'\n'),
+        b"\\0" => Some(b'\0'),
+        &[byte] => Some(byte),
+        _ => None,
+    }
+}
 
-    let results = if args.find(|e| e == "st").is_some() {
-        citymap_single_thread(input)
-    } else {
-        citymap_multi_threaded(input)
+/// Parses a byte range such as `0:1000` into `0..1000`
+fn parse_byte_range(range: &str) -> Option<Range<u64>> {
+    let (start, end) = range.split_once(':')?;
+    Some(start.parse().ok()?..end.parse().ok()?).filter(|range| range.start <= range.end)
+}
+
+/// Every partial result in `input`, `-` being stdin, which may hold several back to back
+fn read_partials(input: &str) -> io::Result<Vec<Citymap>> {
+    let mut reader: BufReader<Box<dyn Read>> = BufReader::new(match input {
+        "-" => Box::new(io::stdin().lock()),
+        path => Box::new(File::open(path)?),
+    });
+    let mut partials = vec![];
+    while !reader.fill_buf()?.is_empty() {
+        partials.push(Citymap::read_from(&mut reader)?);
+    }
+    Ok(partials)
+}
+
+/// Column index counting from zero for a `flag` given one counting from one, exiting on anything else
+fn parse_column(flag: &str, column: Option<String>) -> usize {
+    match column.map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => n - 1,
+        _ => fail(&format!("{flag} expects a positive integer")),
+    }
+}
+
+/// Parses inclusive bounds in degrees such as `-99.9:99.9` into tenths
+fn parse_range(bounds: &str) -> Option<(i64, i64)> {
+    let (min, max) = bounds.split_once(':')?;
+    let tenths = |s: &str| s.parse::<f64>().ok().map(|d| (d * 10.0).round() as i64);
+    Some((tenths(min)?, tenths(max)?)).filter(|(min, max)| min <= max)
+}
+
+/// Periodically reports `progress` on stderr until the returned sender is dropped
+fn spawn_progress_monitor(progress: Progress, total: Option<u64>) -> (Sender<()>, JoinHandle<()>) {
+    let (stop, stopped) = channel::<()>();
+    let monitor = thread::spawn(move || {
+        let start = Instant::now();
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(PROGRESS_INTERVAL) {
+            let done = progress.load(Ordering::Relaxed);
+            let mb = done as f64 / 1e6;
+            match total {
+                Some(total) if done > 0 => {
+                    let fraction = done as f64 / total as f64;
+                    let eta = start.elapsed().as_secs_f64() * (1.0 - fraction) / fraction;
+                    eprint!(
+                        "\rprogress: {mb:.0} MB, {:.1}%, eta {eta:.0}s   ",
+                        fraction * 100.0
+                    );
+                }
+                _ => eprint!("\rprogress: {mb:.0} MB   "),
+            }
+        }
+        eprintln!();
+    });
+    (stop, monitor)
+}
+
+/// `onebrc generate --rows N [--out PATH] [--seed S]`
+fn run_generate(mut args: impl Iterator<Item = String>) {
+    let mut rows = None;
+    let mut out = "measurements.txt".to_owned();
+    let mut seed = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| match args.next() {
+            Some(value) => value,
+            None => {
+                eprintln!("error: {name} expects a value");
+                exit(1);
+            }
+        };
+        let parse_u64 = |name: &str, value: String| match value.parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => {
+                eprintln!("error: {name} expects a non-negative integer");
+                exit(1);
+            }
+        };
+        match arg.as_str() {
+            "--rows" => rows = Some(parse_u64("--rows", value("--rows"))),
+            "--out" => out = value("--out"),
+            "--seed" => seed = Some(parse_u64("--seed", value("--seed"))),
+            _ => {
+                eprintln!("error: unknown argument {arg:?} for generate");
+                exit(1);
+            }
+        }
+    }
+    let Some(rows) = rows else {
+        eprintln!("error: generate requires --rows");
+        exit(1);
     };
+    // Without an explicit seed every run produces a different file
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    });
 
-    print_results(results);
+    if let Err(e) = generate(rows, &out, seed) {
+        eprintln!("error: failed to write {out:?}: {e}");
+        exit(1);
+    }
+}
 
-    println!("{:?}", start.elapsed());
+#[derive(Copy, Clone, Debug)]
+struct PrintOptions {
+    format: OutputFormat,
+    /// Decimals of every printed statistic.
+    /// Values are stored in tenths, so min/mean/max only ever have zeros beyond the first decimal.
+    precision: usize,
+    /// Unit all temperatures are converted to before printing
+    unit: Unit,
+    /// Append the standard deviation to every city
+    stddev: bool,
+    /// Append p50/p90/p99 to every city
+    percentiles: bool,
+    /// Append the most frequent value to every city
+    mode: bool,
+    /// Print only this many cities, those ranking highest by `by`, in that order
+    top: Option<usize>,
+    /// Take the cities ranking lowest for `top` instead, lowest first
+    bottom: bool,
+    by: Rank,
 }
 
-fn citymap_single_thread(path: &str) -> Citymap {
-    let f = File::open(path).unwrap();
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::default(),
+            precision: 1,
+            unit: Unit::default(),
+            stddev: false,
+            percentiles: false,
+            mode: false,
+            top: None,
+            bottom: false,
+            by: Rank::default(),
+        }
+    }
+}
 
-    let mut buf = BufReader::with_capacity(10_usize.pow(8), f);
-    citymap_naive(&mut buf)
+/// Statistic that `--top` and `--bottom` rank cities by
+#[derive(Copy, Clone, Debug, Default)]
+enum Rank {
+    Min,
+    #[default]
+    Mean,
+    Max,
 }
 
-fn citymap_multi_threaded(path: &str) -> Citymap {
-    let cpus = available_parallelism().unwrap().get();
-    let size = File::open(path).unwrap().metadata().unwrap().len();
-    let per_thread = size / cpus as u64;
-
-    let mut index = 0;
-    let mut threads = vec![];
-    let (sender, receiver) = channel();
-    for i in 0..cpus {
-        let range = index..({
-            index += per_thread;
-            index.min(size)
-        });
-        threads.push(citymap_thread(path.to_owned(), range, i, sender.clone()));
-    }
-    let mut ranges = (0..cpus)
-        .into_iter()
-        .map(|_| receiver.recv().unwrap())
-        .collect::<Vec<_>>();
-    ranges.sort_unstable_by_key(|e| e.start);
-    assert!(
-        ranges.windows(2).all(|e| {
-            let first = &e[0];
-            let second = &e[1];
-            first.end == second.start
-        }),
-        "Ranges overlap or have gaps: {ranges:?}"
-    );
-    let results = threads
-        .into_iter()
-        .map(|e| e.join().unwrap())
-        //.map(|e|dbg!(e))
-        .reduce(|mut left, right| {
-            left.merge_with(right);
-            left
-        })
-        .unwrap();
-    results
+impl Rank {
+    fn value(self, city: &City) -> f64 {
+        match self {
+            Rank::Min => city.min(),
+            Rank::Mean => city.mean(),
+            Rank::Max => city.max(),
+        }
+    }
 }
 
-fn citymap_thread(
-    path: String,
-    mut range: Range<u64>,
-    i: usize,
-    range_feedback: Sender<Range<u64>>,
-) -> JoinHandle<Citymap> {
-    thread::Builder::new()
-        .name(format!("process_thread id: {i} assigned: {range:?}"))
-        .spawn(move || {
-            let mut file = File::open(path).unwrap();
-            //println!("Before: {range:?}");
-
-            // Perform alignment of buffer/range at the start
-            {
-                // Skip head alignment for start of file
-                if range.start != 0 {
-                    let mut head = vec![0; 50];
-                    let len = file.read_at(&mut head, range.start).unwrap();
-                    head.truncate(len);
-
-                    for (i, &pos) in head.iter().enumerate() {
-                        if pos == '\n' as u8 {
-                            range.start += i as u64;
-                            break;
-                        }
-                    }
-                }
+#[derive(Copy, Clone, Debug, Default)]
+enum OutputFormat {
+    /// The 1BRC reference format
+    #[default]
+    Text,
+    /// An object keyed by city name
+    Json,
+    /// A header row followed by one row per city
+    Csv,
+    /// The same map as [`OutputFormat::Json`] in MessagePack, with statistics as 64 bit floats
+    Msgpack,
+}
 
-                // tail alignment
-                {
-                    let mut head = vec![0; 50];
-                    let len = file.read_at(&mut head, range.end).unwrap();
-                    head.truncate(len);
+type Stat = (&'static str, fn(&City, Unit) -> f64);
 
-                    for (i, &pos) in head.iter().enumerate() {
-                        if pos == '\n' as u8 {
-                            range.end += i as u64;
-                            break;
-                        }
-                    }
+/// Optional statistics selected by `options` with their output names, in output order
+fn extra_stats(options: PrintOptions) -> Vec<Stat> {
+    let mut extra: Vec<Stat> = vec![];
+    if options.stddev {
+        extra.push(("std", City::std_in));
+    }
+    #[cfg(feature = "percentiles")]
+    if options.percentiles {
+        extra.push(("p50", |c, unit| c.percentile_in(0.5, unit)));
+        extra.push(("p90", |c, unit| c.percentile_in(0.9, unit)));
+        extra.push(("p99", |c, unit| c.percentile_in(0.99, unit)));
+    }
+    #[cfg(feature = "percentiles")]
+    if options.mode {
+        extra.push(("mode", City::mode_in));
+    }
+    extra
+}
+
+/// Writes `map` sorted by city name to `out` in the format selected by `options`.
+/// Names are ordered by their UTF-8 bytes, which for valid UTF-8 is the same as Unicode code point order,
+/// the order of the 1BRC reference. Neither collation nor locale play a part, so `Zürich` follows `Zagreb`.
+fn print_results(out: &mut impl Write, map: Citymap, options: PrintOptions) -> io::Result<()> {
+    // A station without values would print inf/NaN, it can only come from a bug upstream
+    for (city, _) in map.iter().filter(|(_, vals)| vals.is_empty()) {
+        eprintln!("warning: skipping {city:?}, it has no values");
+    }
+    let extra = extra_stats(options);
+    let precision = options.precision;
+    // Without any of the additions, the text output is exactly how the map displays
+    if matches!(options.format, OutputFormat::Text)
+        && options.unit == Unit::Celsius
+        && options.top.is_none()
+        && extra.is_empty()
+    {
+        return writeln!(out, "{map:.precision$}");
+    }
+
+    let mut res = map.into_key_values();
+    res.retain(|(_, vals)| !vals.is_empty());
+    res.sort_unstable_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+    if let Some(top) = options.top {
+        // Stable, so that cities ranking equally stay in name order
+        res.sort_by(|(_, a), (_, b)| {
+            let order = options.by.value(b).total_cmp(&options.by.value(a));
+            match options.bottom {
+                true => order.reverse(),
+                false => order,
+            }
+        });
+        res.truncate(top);
+    }
+    match options.format {
+        OutputFormat::Text => {
+            write!(out, "{{")?;
+            for (i, (city, vals)) in res.into_iter().enumerate() {
+                if i != 0 {
+                    write!(out, ", ")?;
+                }
+                let CityStats { min, mean, max, .. } = vals.stats_in(options.unit);
+                write!(
+                    out,
+                    "{city}={min:.precision$}/{mean:.precision$}/{max:.precision$}"
+                )?;
+                for (_, stat) in &extra {
+                    write!(out, "/{:.precision$}", stat(&vals, options.unit))?;
                 }
             }
+            writeln!(out, "}}")?;
+        }
+        OutputFormat::Json => {
+            write!(out, "{{")?;
+            for (i, (city, vals)) in res.into_iter().enumerate() {
+                if i != 0 {
+                    write!(out, ",")?;
+                }
+                let CityStats {
+                    min,
+                    mean,
+                    max,
+                    count,
+                } = vals.stats_in(options.unit);
+                write!(
+                    out,
+                    "{}:{{\"min\":{min:.precision$},\"mean\":{mean:.precision$},\"max\":{max:.precision$},\"count\":{count}",
+                    json_string(&city)
+                )?;
+                for (name, stat) in &extra {
+                    write!(out, ",\"{name}\":{:.precision$}", stat(&vals, options.unit))?;
+                }
+                write!(out, "}}")?;
+            }
+            writeln!(out, "}}")?;
+        }
+        OutputFormat::Msgpack => {
+            msgpack_map(out, res.len())?;
+            for (city, vals) in res {
+                msgpack_str(out, &city)?;
+                msgpack_map(out, 4 + extra.len())?;
+                let CityStats {
+                    min,
+                    mean,
+                    max,
+                    count,
+                } = vals.stats_in(options.unit);
+                for (name, value) in [("min", min), ("mean", mean), ("max", max)] {
+                    msgpack_str(out, name)?;
+                    msgpack_f64(out, rounded(value, precision))?;
+                }
+                msgpack_str(out, "count")?;
+                msgpack_uint(out, count)?;
+                for (name, stat) in &extra {
+                    msgpack_str(out, name)?;
+                    msgpack_f64(out, rounded(stat(&vals, options.unit), precision))?;
+                }
+            }
+        }
+        OutputFormat::Csv => {
+            write!(out, "city,min,mean,max,count")?;
+            for (name, _) in &extra {
+                write!(out, ",{name}")?;
+            }
+            writeln!(out)?;
+            for (city, vals) in res {
+                let CityStats {
+                    min,
+                    mean,
+                    max,
+                    count,
+                } = vals.stats_in(options.unit);
+                write!(
+                    out,
+                    "{},{min:.precision$},{mean:.precision$},{max:.precision$},{count}",
+                    csv_field(&city)
+                )?;
+                for (_, stat) in &extra {
+                    write!(out, ",{:.precision$}", stat(&vals, options.unit))?;
+                }
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
 
-            // Notify main about alignment
-            range_feedback.send(range.clone()).unwrap();
-            // Ensure we remain within bounds of the designated file range
-            file.seek(SeekFrom::Start(range.start)).unwrap();
-
-            let limited = BufReader::with_capacity(10_usize.pow(5), file);
-            let mut buffered = limited.take(range.end - range.start);
-            citymap_naive(&mut buffered)
-        })
-        .unwrap()
+/// Statistics rounded to `precision` decimals exactly like the text based formats print them
+fn rounded(value: f64, precision: usize) -> f64 {
+    format!("{value:.precision$}").parse().unwrap()
 }
 
-fn citymap_naive(input: &mut impl BufRead) -> Citymap {
-    let mut map = Citymap::new();
-    let mut buf = Vec::with_capacity(50);
-    loop {
-        let read = input.read_until(b'\n', &mut buf).unwrap();
-        // Stream has finished
-        if read == 0 {
-            break;
+/// Writes the header of a MessagePack map with `len` entries
+fn msgpack_map(out: &mut impl Write, len: usize) -> io::Result<()> {
+    match len {
+        0..=15 => out.write_all(&[0x80 | len as u8]),
+        16..=0xffff => {
+            out.write_all(&[0xde])?;
+            out.write_all(&(len as u16).to_be_bytes())
         }
-
-        // Skip over just newline strings that get created by the alignment process
-        if buf == &[b'\n'] {
-            continue;
+        _ => {
+            out.write_all(&[0xdf])?;
+            out.write_all(&(len as u32).to_be_bytes())
         }
+    }
+}
 
-        let mut city = None;
-        let mut val = None;
-        for (i, &char) in buf.iter().enumerate() {
-            if char == b';' {
-                city = Some(&buf[0..i]);
-                val = Some(&buf[(i + 1)..(buf.len() - 1)]);
-                break;
-            }
+fn msgpack_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    let len = s.len();
+    match len {
+        0..=31 => out.write_all(&[0xa0 | len as u8])?,
+        32..=0xff => out.write_all(&[0xd9, len as u8])?,
+        0x100..=0xffff => {
+            out.write_all(&[0xda])?;
+            out.write_all(&(len as u16).to_be_bytes())?;
         }
-        #[cfg(not(feature = "unsafe"))]
-        let entry = map.lookup(std::str::from_utf8(city.unwrap()).unwrap());
+        _ => {
+            out.write_all(&[0xdb])?;
+            out.write_all(&(len as u32).to_be_bytes())?;
+        }
+    }
+    out.write_all(s.as_bytes())
+}
 
-        #[cfg(feature = "unsafe")]
-        let entry = map.lookup(unsafe { std::str::from_utf8_unchecked(city.unwrap()) });
+fn msgpack_f64(out: &mut impl Write, value: f64) -> io::Result<()> {
+    out.write_all(&[0xcb])?;
+    out.write_all(&value.to_be_bytes())
+}
 
-        entry.add_new(val.unwrap());
-        buf.clear();
+/// Writes `value` in the smallest unsigned integer encoding that holds it
+fn msgpack_uint(out: &mut impl Write, value: u64) -> io::Result<()> {
+    match value {
+        0..=0x7f => out.write_all(&[value as u8]),
+        0x80..=0xff => out.write_all(&[0xcc, value as u8]),
+        0x100..=0xffff => {
+            out.write_all(&[0xcd])?;
+            out.write_all(&(value as u16).to_be_bytes())
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.write_all(&[0xce])?;
+            out.write_all(&(value as u32).to_be_bytes())
+        }
+        _ => {
+            out.write_all(&[0xcf])?;
+            out.write_all(&value.to_be_bytes())
+        }
     }
-    map
 }
 
-fn print_results(map: Citymap) {
-    let mut res = map.into_key_values();
-    res.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
-    print!("{{");
-    for (city, vals) in res {
-        let min = vals.min();
-        let mean = vals.mean();
-        let max = vals.max();
-        print!("{city}={min:.1}/{mean:.1}/{max:.1}, ")
-    }
-    println!("}}");
+/// Quotes `s` if it contains characters that are special to CSV
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Quotes and escapes `s` as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
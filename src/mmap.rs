@@ -0,0 +1,113 @@
+//! Read-only memory mapping of whole files, talking to libc directly to stay dependency free.
+//! Only built for 64-bit targets, where `off_t` is 64 bits wide and every file size fits in a `usize`.
+
+use std::ffi::{c_int, c_void};
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, Range};
+use std::os::fd::AsRawFd;
+use std::ptr;
+
+const PROT_READ: c_int = 1;
+const MAP_PRIVATE: c_int = 2;
+const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+#[cfg(target_os = "linux")]
+const MADV_SEQUENTIAL: c_int = 2;
+#[cfg(target_os = "linux")]
+const MADV_WILLNEED: c_int = 3;
+#[cfg(target_os = "linux")]
+const SC_PAGESIZE: c_int = 30;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        // off_t
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    #[cfg(target_os = "linux")]
+    fn madvise(addr: *mut c_void, len: usize, advice: c_int) -> c_int;
+    #[cfg(target_os = "linux")]
+    fn sysconf(name: c_int) -> std::ffi::c_long;
+}
+
+pub struct Mmap {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+// The mapping is read-only, so sharing it across threads is sound
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    pub fn map(file: &File) -> io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        // Mapping zero bytes is an error, an empty file simply yields an empty slice
+        if len == 0 {
+            return Ok(Self {
+                ptr: ptr::null_mut(),
+                len,
+            });
+        }
+
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ,
+                MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    /// Tells the kernel that `range` is about to be read front to back, so it reads ahead aggressively.
+    /// Only a hint, failures are ignored and other platforms do nothing.
+    pub fn advise_sequential(&self, range: Range<usize>) {
+        #[cfg(target_os = "linux")]
+        if !range.is_empty() {
+            // madvise wants a page aligned address, and the mapping itself starts on a page boundary
+            let page = unsafe { sysconf(SC_PAGESIZE) } as usize;
+            let start = range.start - range.start % page;
+            let addr = unsafe { self.ptr.byte_add(start) };
+            let len = range.end.min(self.len) - start;
+            unsafe {
+                madvise(addr, len, MADV_SEQUENTIAL);
+                madvise(addr, len, MADV_WILLNEED);
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = range;
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe {
+                munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
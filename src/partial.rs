@@ -0,0 +1,156 @@
+//! A compact binary encoding of [`Citymap`], for partial results that are merged by another process or kept on disk.
+//!
+//! All integers are little endian. After [`MAGIC`] follow the histogram resolution in tenths as `u32`, the out
+//! of range count and the number of cities as `u64`, then per city its name as a `u32` length of at most
+//! [`MAX_NAME_BYTES`] and UTF-8 bytes, `min` and `max` as `i64`, `sum` and `sum_squares` as `i128`,
+//! `occurrences` as `u64` and the histogram as a `u32` length and `u64` counts, the length being zero without
+//! the `percentiles` feature.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+#[cfg(feature = "percentiles")]
+use crate::histogram_buckets;
+use crate::{City, Citymap};
+
+/// Identifies the encoding and its version
+const MAGIC: &[u8; 8] = b"1BRCMAP\x02";
+
+/// Longest name an encoded map may hold, so a corrupted length cannot make a reader allocate gigabytes
+const MAX_NAME_BYTES: usize = 1 << 16;
+
+impl Citymap {
+    /// Encodes every city and the out of range count into `out`, failing on names longer than 64 KiB
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        if self
+            .slots
+            .iter()
+            .flatten()
+            .any(|slot| slot.name.len() > MAX_NAME_BYTES)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "station name too long for a partial result",
+            ));
+        }
+        out.write_all(MAGIC)?;
+        out.write_all(&self.histogram_resolution.to_le_bytes())?;
+        out.write_all(&self.out_of_range.to_le_bytes())?;
+        out.write_all(&(self.len as u64).to_le_bytes())?;
+        for slot in self.slots.iter().flatten() {
+            let (name, city) = (&slot.name, &slot.city);
+            out.write_all(&(name.len() as u32).to_le_bytes())?;
+            out.write_all(name.as_bytes())?;
+            out.write_all(&city.min.to_le_bytes())?;
+            out.write_all(&city.max.to_le_bytes())?;
+            out.write_all(&city.sum.to_le_bytes())?;
+            out.write_all(&city.sum_squares.to_le_bytes())?;
+            out.write_all(&city.occurrences.to_le_bytes())?;
+            #[cfg(feature = "percentiles")]
+            {
+                out.write_all(&(city.histogram.len() as u32).to_le_bytes())?;
+                for count in city.histogram.iter() {
+                    out.write_all(&count.to_le_bytes())?;
+                }
+            }
+            #[cfg(not(feature = "percentiles"))]
+            out.write_all(&0u32.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a map written by [`Citymap::write_to`], reading no further than its end
+    pub fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a partial result"));
+        }
+        let resolution = u32::from_le_bytes(read_array(input)?);
+        if resolution == 0 {
+            return Err(invalid("partial result has histogram buckets of no width"));
+        }
+        let mut map = Citymap {
+            out_of_range: u64::from_le_bytes(read_array(input)?),
+            ..Citymap::new().with_histogram_resolution(resolution)
+        };
+        let len = u64::from_le_bytes(read_array(input)?);
+        for _ in 0..len {
+            let name_len = u32::from_le_bytes(read_array(input)?) as usize;
+            if name_len > MAX_NAME_BYTES {
+                return Err(invalid("partial result holds an overlong name"));
+            }
+            let mut name = vec![0; name_len];
+            input.read_exact(&mut name)?;
+            let min = i64::from_le_bytes(read_array(input)?);
+            let max = i64::from_le_bytes(read_array(input)?);
+            let sum = i128::from_le_bytes(read_array(input)?);
+            let sum_squares = i128::from_le_bytes(read_array(input)?);
+            let occurrences = u64::from_le_bytes(read_array(input)?);
+            let buckets = u32::from_le_bytes(read_array(input)?) as usize;
+            #[cfg(feature = "percentiles")]
+            let histogram = {
+                if buckets != histogram_buckets(resolution as i64) {
+                    return Err(invalid("partial result lacks matching histograms"));
+                }
+                (0..buckets)
+                    .map(|_| read_array(input).map(u64::from_le_bytes))
+                    .collect::<io::Result<_>>()?
+            };
+            // Histograms are of no use without the feature
+            #[cfg(not(feature = "percentiles"))]
+            io::copy(&mut input.take(buckets as u64 * 8), &mut io::sink())?;
+            let decoded = City {
+                min,
+                max,
+                sum,
+                sum_squares,
+                occurrences,
+                #[cfg(feature = "percentiles")]
+                histogram,
+                #[cfg(feature = "percentiles")]
+                bucket_width: resolution as i64,
+            };
+            if decoded.is_empty() || decoded.min > decoded.max {
+                return Err(invalid("partial result holds a corrupted city"));
+            }
+
+            let Some(city) = map.lookup(&name) else {
+                return Err(invalid("partial result holds a name that is not UTF-8"));
+            };
+            if !city.is_empty() {
+                return Err(invalid("partial result holds a city twice"));
+            }
+            *city = decoded;
+        }
+        Ok(map)
+    }
+
+    /// Writes the map to a new file at `path`, replacing any existing one
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    /// Reads back a map written by [`Citymap::save`]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let map = Self::read_from(&mut input)?;
+        if !input.fill_buf()?.is_empty() {
+            return Err(invalid("trailing data after the saved map"));
+        }
+        Ok(map)
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,26 @@
+//! A byte order mark at the start of a file is no part of the first station name.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{aggregate_file, aggregate_reader, AggregateConfig};
+
+const CONTENTS: &str = "\u{FEFF}Oslo;1.5\nLima;20.0\nOslo;2.5\n";
+
+#[test]
+fn leading_bom_is_skipped() {
+    let file = TempFile::new("bom", CONTENTS);
+    let path = file.path();
+
+    let expected =
+        aggregate_reader(&mut &CONTENTS.as_bytes()[3..], &AggregateConfig::default()).unwrap();
+    assert_eq!(
+        aggregate_reader(&mut CONTENTS.as_bytes(), &AggregateConfig::default()).unwrap(),
+        expected
+    );
+    for threads in [1, 2, 3] {
+        let map = aggregate_file(path, &AggregateConfig::new().threads(threads)).unwrap();
+        assert_eq!(map.get("Oslo").map(|stats| stats.count), Some(2));
+        assert_eq!(map, expected, "{threads} threads");
+    }
+}
@@ -0,0 +1,37 @@
+//! Lines are parsed in place in the read buffer, so whichever way its refills cut them they have to
+//! aggregate like they do when read in one go, including at a byte limit.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{AggregateConfig, City};
+
+fn sorted(path: &str, buffer_bytes: usize, limit: Option<u64>) -> Vec<(String, City)> {
+    let mut config = AggregateConfig::new().threads(1).buffer_bytes(buffer_bytes);
+    if let Some(limit) = limit {
+        config = config.limit(limit);
+    }
+    common::sorted(path, &config)
+}
+
+#[test]
+fn buffer_refills_do_not_change_the_results() {
+    // Lines of varying length, the last one without a terminator
+    let contents = (0..60)
+        .map(|i| format!("{};{}.{}", "Station".repeat(i % 4 + 1), i % 30, i % 10))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let file = TempFile::new("buffer", &contents);
+    let path = file.path();
+
+    for limit in [None, Some(1), Some(100), Some(contents.len() as u64 - 1)] {
+        let expected = sorted(path, 1 << 16, limit);
+        for buffer_bytes in 1..=40 {
+            assert_eq!(
+                sorted(path, buffer_bytes, limit),
+                expected,
+                "{buffer_bytes} byte buffer, limit {limit:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! A map sized up front has to take that many stations without growing, and lookups of known ones never grow it.
+
+use onebrc::{Citymap, EXPECTED_STATIONS};
+
+#[test]
+fn with_capacity_holds_its_stations_without_growing() {
+    for stations in [1, 3, 1000, EXPECTED_STATIONS] {
+        let mut map = Citymap::<String>::with_capacity(stations);
+        let capacity = map.capacity();
+        assert!(capacity >= stations, "{capacity} for {stations} stations");
+        for i in 0..stations {
+            map.lookup(format!("Station {i}").as_bytes()).unwrap();
+        }
+        assert_eq!(map.capacity(), capacity, "{stations} stations");
+        assert_eq!(map.len(), stations);
+    }
+    assert_eq!(Citymap::<String>::with_capacity(0).capacity(), 0);
+}
+
+#[test]
+fn looking_up_known_stations_never_grows_a_full_map() {
+    for stations in [1, 3, 1000, EXPECTED_STATIONS] {
+        let mut map = Citymap::<String>::with_capacity(stations);
+        let capacity = map.capacity();
+        let names = (0..capacity)
+            .map(|i| format!("Station {i}"))
+            .collect::<Vec<_>>();
+        for _ in 0..2 {
+            for name in &names {
+                map.lookup(name.as_bytes()).unwrap().add_new_value(1);
+            }
+        }
+        assert_eq!(map.capacity(), capacity, "{stations} stations");
+        assert_eq!(map.len(), capacity);
+        assert_eq!(map.rows(), 2 * capacity as u64);
+    }
+}
@@ -0,0 +1,74 @@
+//! Every line has to be counted exactly once, including the one a chunk boundary falls into.
+//! Workers split files into `4 * threads` chunks of `size / chunks` bytes, so a file of exactly
+//! `4 * threads * CHUNK` bytes puts the first boundary at `CHUNK`.
+
+mod common;
+
+use common::{sorted, TempFile};
+use onebrc::{aggregate_file, AggregateConfig};
+
+const CHUNK: usize = 64;
+const TARGET: &str = "Target;2.5\n";
+const FILLER: &str = "f;1.0\n";
+
+/// A line of exactly `len` bytes for the station made of `c`
+fn line(c: char, len: usize) -> String {
+    format!("{};1.0\n", c.to_string().repeat(len - 5))
+}
+
+#[test]
+fn line_on_chunk_boundary_is_counted_once() {
+    let file = TempFile::new("boundary", "");
+    let path = file.path();
+    for threads in [2, 3, 7] {
+        let size = 4 * threads * CHUNK;
+        // Shifts the target line across the first boundary, one byte at a time
+        for before in CHUNK - TARGET.len()..=CHUNK {
+            let mut contents = line('p', before) + TARGET;
+            let fillers = (size - contents.len() - 6) / FILLER.len();
+            contents += &FILLER.repeat(fillers);
+            contents += &line('z', size - contents.len());
+            assert_eq!(contents.len(), size);
+            file.write(&contents);
+
+            let map = aggregate_file(path, &AggregateConfig::new().threads(threads)).unwrap();
+            let counts = map
+                .into_key_values()
+                .into_iter()
+                .map(|(name, city)| (name, city.count()))
+                .collect::<Vec<_>>();
+            let count = |name: &str| counts.iter().find(|(n, _)| n == name).map(|&(_, c)| c);
+            let context = format!("{threads} threads, target at byte {before}");
+            assert_eq!(count("Target"), Some(1), "{context}");
+            assert_eq!(count(&"p".repeat(before - 5)), Some(1), "{context}");
+            assert_eq!(count("f"), Some(fillers as u64), "{context}");
+            assert_eq!(counts.len(), 4, "{context}: {counts:?}");
+        }
+    }
+}
+
+#[test]
+fn lines_longer_than_a_chunk_straddle_boundaries() {
+    let file = TempFile::new("long-lines", "");
+    for lines in 1..12 {
+        // Names of up to 300 bytes, so that boundaries fall deep into them
+        let contents = (0..lines)
+            .map(|i| {
+                format!(
+                    "{};{i}.5\n",
+                    char::from(b'a' + i as u8).to_string().repeat(60 + i * 22)
+                )
+            })
+            .collect::<String>();
+        file.write(&contents);
+        let expected = sorted(file.path(), &AggregateConfig::new().threads(1));
+        assert_eq!(expected.len(), lines);
+        for threads in [2, 3, 7] {
+            assert_eq!(
+                sorted(file.path(), &AggregateConfig::new().threads(threads)),
+                expected,
+                "{lines} lines on {threads} threads"
+            );
+        }
+    }
+}
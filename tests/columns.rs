@@ -0,0 +1,63 @@
+//! Lines of more than two fields have to aggregate like the two selected columns on their own.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{validate_reader, AggregateConfig, City, LineFormat, ParseError};
+
+fn sorted(path: &str, threads: usize, format: LineFormat) -> Vec<(String, City)> {
+    common::sorted(
+        path,
+        &AggregateConfig::new().threads(threads).format(format),
+    )
+}
+
+#[test]
+fn selected_columns_match_a_two_column_file() {
+    let values = (0..500)
+        .map(|i| {
+            (
+                format!("Station {}", i % 13),
+                format!("{}.{}", i % 97 - 48, i % 10),
+            )
+        })
+        .collect::<Vec<_>>();
+    let lines = |line: fn(&(String, String), usize) -> String| {
+        let lines = values.iter().enumerate().map(|(i, v)| line(v, i));
+        lines.collect::<Vec<_>>().join("\n")
+    };
+    let pairs = TempFile::new("pairs", lines(|(city, val), _| format!("{city};{val}")));
+    let wide = TempFile::new(
+        "wide",
+        lines(|(city, val), i| format!("sensor {};{val};{city};2024-01-{i:02}", i % 7)),
+    );
+
+    let columns = LineFormat {
+        key_column: 2,
+        value_column: 1,
+        ..LineFormat::default()
+    };
+    let expected = sorted(pairs.path(), 1, LineFormat::default());
+    assert_eq!(expected.len(), 13);
+    for threads in [1, 2, 5] {
+        assert_eq!(
+            sorted(wide.path(), threads, columns),
+            expected,
+            "{threads} threads"
+        );
+    }
+}
+
+#[test]
+fn lines_lacking_a_selected_column_are_malformed() {
+    let format = LineFormat {
+        value_column: 3,
+        ..LineFormat::default()
+    };
+    let validation = validate_reader(&mut "Oslo;a;b;1.5\nOslo;a;1.5\n".as_bytes(), format).unwrap();
+    assert_eq!((validation.lines, validation.malformed), (2, 1));
+    assert!(matches!(
+        validation.errors[..],
+        [(2, ParseError::MissingSeparator { .. })]
+    ));
+}
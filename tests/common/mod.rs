@@ -0,0 +1,49 @@
+//! Fixtures shared by the integration tests, which each pull this in with `mod common;`.
+#![allow(dead_code)]
+
+use std::env::temp_dir;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use onebrc::{aggregate_file, AggregateConfig, City};
+
+/// Files created by this test binary so far, keeping those of concurrent tests apart
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// A file in the temp directory that is removed when dropped, even by a panicking test
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    /// A new file holding `contents`, `name` telling the files of different tests apart when left open
+    pub fn new(name: &str, contents: impl AsRef<[u8]>) -> Self {
+        let n = CREATED.fetch_add(1, Ordering::Relaxed);
+        let file = Self(temp_dir().join(format!("onebrc-{name}-{}-{n}", process::id())));
+        file.write(contents);
+        file
+    }
+
+    /// Replaces the contents of the file
+    pub fn write(&self, contents: impl AsRef<[u8]>) {
+        fs::write(&self.0, contents).unwrap();
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The cities of `path` aggregated with `config`, sorted by name
+pub fn sorted(path: &str, config: &AggregateConfig) -> Vec<(String, City)> {
+    let map = aggregate_file(path, config).unwrap();
+    let mut cities = map.into_key_values();
+    cities.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    cities
+}
@@ -0,0 +1,66 @@
+//! Compressed inputs have to aggregate like the plain file, and a failing decompressor has to be an error.
+#![cfg(any(feature = "gzip", feature = "zstd"))]
+
+mod common;
+
+use std::process::Command;
+
+use common::TempFile;
+use onebrc::{aggregate_file, aggregate_reader, validate_file, AggregateConfig, ParseError};
+
+const LINES: &str = "Oslo;1.0\nLima;-20.5\nOslo;3.5\n";
+
+#[test]
+#[cfg(feature = "gzip")]
+fn gzip_input_matches_plain_input() {
+    let plain = TempFile::new("plain", LINES);
+    let gzip = Command::new("gzip")
+        .args(["-c", plain.path()])
+        .output()
+        .unwrap();
+    assert!(gzip.status.success());
+    let gzipped = TempFile::new("gzipped", gzip.stdout);
+
+    let expected = aggregate_reader(&mut LINES.as_bytes(), &AggregateConfig::default()).unwrap();
+    assert_eq!(
+        aggregate_file(gzipped.path(), &AggregateConfig::new()).unwrap(),
+        expected
+    );
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn corrupt_gzip_input_is_an_error() {
+    let file = TempFile::new("corrupt-gzip", b"\x1f\x8b\x08\x00garbage");
+    assert!(validate_file(file.path(), &AggregateConfig::new()).is_err());
+    assert!(matches!(
+        aggregate_file(file.path(), &AggregateConfig::new()),
+        Err(ParseError::Io(_))
+    ));
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn zstd_input_validates_and_fails_like_gzip() {
+    let plain = TempFile::new("plain", LINES);
+    let zstd = Command::new("zstd")
+        .args(["-qc", plain.path()])
+        .output()
+        .unwrap();
+    assert!(zstd.status.success());
+    let compressed = TempFile::new("zstd", zstd.stdout);
+    let expected = aggregate_reader(&mut LINES.as_bytes(), &AggregateConfig::default()).unwrap();
+    assert_eq!(
+        aggregate_file(compressed.path(), &AggregateConfig::new()).unwrap(),
+        expected
+    );
+    let validation = validate_file(compressed.path(), &AggregateConfig::new()).unwrap();
+    assert_eq!((validation.lines, validation.malformed), (3, 0));
+
+    let corrupt = TempFile::new("corrupt-zstd", b"\x28\xb5\x2f\xfdgarbage");
+    assert!(validate_file(corrupt.path(), &AggregateConfig::new()).is_err());
+    assert!(matches!(
+        aggregate_file(corrupt.path(), &AggregateConfig::new()),
+        Err(ParseError::Io(_))
+    ));
+}
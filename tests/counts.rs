@@ -0,0 +1,22 @@
+//! Counts are 64 bit, so a city has to keep counting, and ranking its percentiles, past `u32::MAX` values.
+
+use onebrc::City;
+
+#[test]
+fn counts_go_beyond_u32() {
+    let mut city = City::default();
+    city.add_new_value(-5);
+    city.add_new_value(15);
+    // Doubling by merging with itself, as adding 2^33 values one by one would take minutes
+    for _ in 0..32 {
+        city.add_result(city.clone());
+    }
+    assert_eq!(city.count(), 1 << 33);
+    assert!(city.count() > u32::MAX as u64);
+    assert_eq!(city.mean(), 0.5);
+    #[cfg(feature = "percentiles")]
+    {
+        assert_eq!(city.percentile(0.5), -0.5);
+        assert_eq!(city.percentile(0.51), 1.5);
+    }
+}
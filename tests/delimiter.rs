@@ -0,0 +1,39 @@
+//! Tab separated files have to aggregate exactly like the same lines separated by `;`.
+
+mod common;
+
+use std::process::Command;
+
+use common::{sorted, TempFile};
+use onebrc::{AggregateConfig, LineFormat};
+
+const LINES: &str = "Oslo;1.0\nSão Paulo;-20.5\nOslo;3.5\nSt. John's;0.0\n";
+
+#[test]
+fn tab_separated_lines_match_semicolon_separated_ones() {
+    let semicolons = TempFile::new("semicolons", LINES);
+    let tabs = TempFile::new("tabs", LINES.replace(';', "\t"));
+    let tab = LineFormat {
+        delimiter: b'\t',
+        ..LineFormat::default()
+    };
+    let expected = sorted(semicolons.path(), &AggregateConfig::new().threads(1));
+    assert_eq!(expected.len(), 3);
+    for threads in [1, 3] {
+        let config = AggregateConfig::new().threads(threads).format(tab);
+        assert_eq!(sorted(tabs.path(), &config), expected, "{threads} threads");
+    }
+
+    let stdout = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        output.stdout
+    };
+    assert_eq!(
+        stdout(&["--delimiter", "\\t", tabs.path()]),
+        stdout(&[semicolons.path()])
+    );
+}
@@ -0,0 +1,23 @@
+//! An empty input has no lines to split between workers, which must give an empty result rather than a panic.
+
+mod common;
+
+use std::process::Command;
+
+use common::TempFile;
+use onebrc::{aggregate_file, AggregateConfig};
+
+#[test]
+fn empty_file_gives_empty_results() {
+    let file = TempFile::new("empty", "");
+    for threads in [1, 4] {
+        let map = aggregate_file(file.path(), &AggregateConfig::new().threads(threads)).unwrap();
+        assert_eq!((map.len(), map.rows()), (0, 0), "{threads} threads");
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(["--threads", "4", file.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"{}\n");
+}
@@ -0,0 +1,53 @@
+//! Following a file has to pick up lines appended to it, including one whose end is written later.
+
+mod common;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::TempFile;
+use onebrc::{aggregate_reader, follow_file, AggregateConfig};
+
+#[test]
+fn appended_lines_are_aggregated_until_stopped() {
+    let appends = [
+        "Oslo;1.0\nLima;2",
+        "0.5\nOslo;-3.",
+        "0\nLima;1.5\n",
+        "Oslo;9",
+    ];
+    let file = TempFile::new("follow", "Oslo;4.0\n");
+
+    let mut snapshots = vec![];
+    let mut pending = appends.iter();
+    let stop = Arc::new(AtomicBool::new(false));
+    let map = follow_file(
+        file.path(),
+        &AggregateConfig::new().buffer_bytes(64).stop(stop.clone()),
+        Duration::ZERO,
+        |map| {
+            snapshots.push(map.rows());
+            // Every snapshot is taken while caught up, so it is the moment to write the next piece
+            match pending.next() {
+                Some(append) => {
+                    let mut file = OpenOptions::new().append(true).open(file.path()).unwrap();
+                    file.write_all(append.as_bytes()).unwrap();
+                }
+                None => stop.store(true, Ordering::Relaxed),
+            }
+        },
+    )
+    .unwrap();
+
+    // The trailing `Oslo;9` was never terminated, so it is still being written
+    let expected = aggregate_reader(
+        &mut "Oslo;4.0\nOslo;1.0\nLima;20.5\nOslo;-3.0\nLima;1.5\n".as_bytes(),
+        &AggregateConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(map, expected);
+    assert_eq!(snapshots, [1, 2, 3, 5, 5]);
+}
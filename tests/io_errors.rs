@@ -0,0 +1,24 @@
+//! Inputs that cannot be opened or read have to surface as errors instead of panics.
+
+use std::time::Duration;
+
+use onebrc::{aggregate_file, aggregate_range, follow_file, AggregateConfig, ParseError};
+
+const MISSING: &str = "/nonexistent/onebrc/measurements.txt";
+
+#[test]
+fn missing_files_are_io_errors() {
+    for threads in [1, 4] {
+        let config = AggregateConfig::new().threads(threads);
+        assert!(
+            matches!(aggregate_file(MISSING, &config), Err(ParseError::Io(_))),
+            "{threads} threads"
+        );
+    }
+    assert!(matches!(
+        aggregate_range(MISSING, 0..100, &AggregateConfig::new()),
+        Err(ParseError::Io(_))
+    ));
+    let follow = follow_file(MISSING, &AggregateConfig::new(), Duration::ZERO, |_| {});
+    assert!(matches!(follow, Err(ParseError::Io(_))));
+}
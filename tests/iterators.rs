@@ -0,0 +1,26 @@
+//! Maps taken apart into their cities and collected again have to keep every value.
+
+use onebrc::{aggregate_reader, AggregateConfig, Citymap};
+
+const LINES: &str = "Oslo;-3.0\nLima;20.5\nOslo;4.5\nKyiv;1.0\nLima;18.0\n";
+
+#[test]
+fn collecting_partials_folds_duplicate_cities() {
+    let whole = aggregate_reader(&mut LINES.as_bytes(), &AggregateConfig::default()).unwrap();
+    let (first, second) = LINES.split_at(LINES.find("Oslo;4.5").unwrap());
+    let first = aggregate_reader(&mut first.as_bytes(), &AggregateConfig::default()).unwrap();
+    let second = aggregate_reader(&mut second.as_bytes(), &AggregateConfig::default()).unwrap();
+
+    let collected = first
+        .clone()
+        .into_iter()
+        .chain(second.clone())
+        .collect::<Citymap>();
+    assert_eq!(collected, whole);
+    assert_eq!(collected.get("Oslo").unwrap().count, 2);
+
+    let mut extended = first;
+    extended.extend(second);
+    assert_eq!(extended, whole);
+    assert_eq!(whole.clone().into_iter().count(), 3);
+}
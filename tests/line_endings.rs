@@ -0,0 +1,43 @@
+//! The way the lines of a file end must not change what they aggregate to.
+
+mod common;
+
+use common::{sorted, TempFile};
+use onebrc::AggregateConfig;
+
+const LINES: &str = "Oslo;1.0\nLima;-20.5\nOslo;3.5\nKyiv;0.0\n";
+
+#[test]
+fn crlf_lines_match_lf_lines() {
+    let lf = TempFile::new("lf", LINES);
+    let crlf = TempFile::new("crlf", LINES.replace('\n', "\r\n"));
+    let expected = sorted(lf.path(), &AggregateConfig::new().threads(1));
+    assert_eq!(expected.len(), 3);
+    for threads in [1, 3] {
+        let config = AggregateConfig::new().threads(threads);
+        assert_eq!(sorted(crlf.path(), &config), expected, "{threads} threads");
+    }
+}
+
+#[test]
+fn last_line_without_terminator_is_counted() {
+    let unterminated = TempFile::new("unterminated", LINES.trim_end());
+    let expected = sorted(
+        TempFile::new("terminated", LINES).path(),
+        &AggregateConfig::new().threads(1),
+    );
+    for threads in [1, 2, 4] {
+        let config = AggregateConfig::new().threads(threads);
+        assert_eq!(
+            sorted(unterminated.path(), &config),
+            expected,
+            "{threads} threads"
+        );
+    }
+    let map = onebrc::aggregate_reader(
+        &mut LINES.trim_end().as_bytes(),
+        &AggregateConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(map.get("Kyiv").map(|stats| stats.count), Some(1));
+}
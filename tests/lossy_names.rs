@@ -0,0 +1,75 @@
+//! With lossy names, invalid UTF-8 in a station name must no longer abort the run.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{aggregate_file, validate_reader, AggregateConfig, Citymap, LineFormat, ParseError};
+
+/// Zürich in Latin-1 and in a second broken encoding, which both become `Z\u{FFFD}rich`
+const CONTENTS: &[u8] = b"Z\xfcrich;1.0\nOslo;2.0\nZ\xfdrich;3.0\nZ\xfcrich;5.0\n";
+
+fn aggregate(
+    path: &str,
+    threads: usize,
+    format: LineFormat,
+) -> Result<Vec<(String, u64)>, ParseError> {
+    let map = aggregate_file(
+        path,
+        &AggregateConfig::new().threads(threads).format(format),
+    )?;
+    let mut counts = map
+        .into_key_values()
+        .into_iter()
+        .map(|(name, city)| (name, city.count()))
+        .collect::<Vec<_>>();
+    counts.sort_unstable();
+    Ok(counts)
+}
+
+#[test]
+// The unsafe feature skips validating names, so nothing is replaced
+#[cfg_attr(feature = "unsafe", ignore)]
+fn invalid_names_are_replaced_and_merged() {
+    let file = TempFile::new("lossy", CONTENTS);
+    let path = file.path();
+    let lossy = LineFormat {
+        lossy_names: true,
+        ..LineFormat::default()
+    };
+
+    let expected = vec![("Oslo".to_owned(), 1), ("Z\u{FFFD}rich".to_owned(), 3)];
+    for threads in [1, 2, 3] {
+        assert!(matches!(
+            aggregate(path, threads, LineFormat::default()),
+            Err(ParseError::InvalidUtf8 { .. } | ParseError::Worker { .. })
+        ));
+        assert_eq!(
+            aggregate(path, threads, lossy).unwrap(),
+            expected,
+            "{threads} threads"
+        );
+    }
+    assert!(validate_reader(&mut &CONTENTS[..], lossy)
+        .unwrap()
+        .is_valid());
+}
+
+#[test]
+#[cfg_attr(feature = "unsafe", ignore)]
+fn replaced_names_merge_with_their_valid_spelling() {
+    let names: [&[u8]; 4] = [
+        b"Z\xfcrich",
+        "Z\u{FFFD}rich".as_bytes(),
+        b"Z\xfdrich",
+        b"Z\xfcrich",
+    ];
+    let mut owned = Citymap::<String>::new();
+    let mut borrowed = Citymap::<&[u8]>::new();
+    for (i, name) in names.into_iter().enumerate() {
+        owned.lookup_lossy(name).add_new_value(i as i64);
+        borrowed.lookup_lossy(name).add_new_value(i as i64);
+    }
+    assert_eq!(owned.len(), 1);
+    assert_eq!(owned.get("Z\u{FFFD}rich").unwrap().count, 4);
+    assert_eq!(borrowed.into_owned(), owned);
+}
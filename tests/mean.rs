@@ -0,0 +1,62 @@
+//! The mean is computed from an exact integer sum, so it has to stay correctly rounded however many values there are.
+
+use onebrc::{City, Unit};
+
+/// A city holding `value` tenths `2^doublings` times, built by merging it with itself
+fn repeated(value: i64, doublings: u32) -> City {
+    let mut city = City::default();
+    city.add_new_value(value);
+    for _ in 0..doublings {
+        city.add_result(city.clone());
+    }
+    city
+}
+
+#[test]
+fn mean_matches_floating_point_on_ordinary_data() {
+    let mut city = City::default();
+    let values = (0..10_000)
+        .map(|i| (i * 37) % 1999 - 999)
+        .collect::<Vec<i64>>();
+    for &value in &values {
+        city.add_new_value(value);
+    }
+    let float_mean = values.iter().sum::<i64>() as f64 / values.len() as f64 / 10.0;
+    assert!(
+        (city.mean() - float_mean).abs() <= 0.05,
+        "{} vs {float_mean}",
+        city.mean()
+    );
+}
+
+#[test]
+fn mean_stays_exact_beyond_float_precision() {
+    // 2^60 values of 0.1 and as many of 0.0 average to exactly 0.05, which rounds up
+    let mut city = repeated(1, 60);
+    city.add_result(repeated(0, 60));
+    assert_eq!(city.mean(), 0.1);
+    // One more value of -0.1 pulls the mean just below 0.05. Dividing the sum and count as floats
+    // rounds both back to powers of two and still gives 0.05, and so would a running float mean.
+    city.add_new_value(-1);
+    assert_eq!(city.count(), (1 << 61) + 1);
+    assert_eq!(city.mean(), 0.0);
+}
+
+#[test]
+fn sums_beyond_i64_stay_exact() {
+    // 2^62 values of 99.9 sum to about 4.6 * 10^21 tenths, far beyond what an i64 holds
+    let mut city = repeated(999, 62);
+    assert_eq!(city.mean(), 99.9);
+    assert_eq!(city.std(), 0.0);
+    city.add_result(repeated(-999, 62));
+    assert_eq!(city.count(), 1 << 63);
+    assert_eq!(city.mean(), 0.0);
+    assert_eq!((city.min(), city.max()), (-99.9, 99.9));
+}
+
+#[test]
+fn mean_of_an_empty_city_is_nan() {
+    let city = City::default();
+    assert!(city.mean().is_nan());
+    assert!(city.mean_in(Unit::Fahrenheit).is_nan());
+}
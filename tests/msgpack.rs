@@ -0,0 +1,43 @@
+//! Byte exact check of the MessagePack output against a hand encoded map.
+
+mod common;
+
+use std::process::Command;
+
+use common::TempFile;
+
+/// A MessagePack fixstr
+fn str(s: &str) -> Vec<u8> {
+    [&[0xa0 | s.len() as u8], s.as_bytes()].concat()
+}
+
+fn f64(value: f64) -> Vec<u8> {
+    [&[0xcb], &value.to_be_bytes()[..]].concat()
+}
+
+#[test]
+fn msgpack_output_matches_hand_encoding() {
+    let file = TempFile::new("msgpack", "Oslo;1.0\nOslo;2.5\nOslo;-0.4\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(["--format", "msgpack"])
+        .arg(file.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let expected = [
+        vec![0x81],
+        str("Oslo"),
+        vec![0x84],
+        str("min"),
+        f64(-0.4),
+        str("mean"),
+        f64(1.0),
+        str("max"),
+        f64(2.5),
+        str("count"),
+        vec![0x03],
+    ]
+    .concat();
+    assert_eq!(output.stdout, expected);
+}
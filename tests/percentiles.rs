@@ -0,0 +1,70 @@
+//! Percentiles come from a histogram, exact at the default resolution of a tenth and within a bucket when coarser.
+#![cfg(feature = "percentiles")]
+
+use onebrc::{aggregate_reader, AggregateConfig, City, Citymap, Unit};
+
+/// Smallest of `sorted` such that at least `p` of its values are less or equal to it, in degrees
+fn exact_percentile(sorted: &[i64], p: f64) -> f64 {
+    let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1] as f64 / 10.0
+}
+
+#[test]
+fn percentiles_are_exact_at_tenths_and_within_a_bucket_when_coarser() {
+    let mut values = (0..5_000)
+        .map(|i| (i * 7919) % 1999 - 999)
+        .collect::<Vec<i64>>();
+    let fill = |city: &mut City| values.iter().for_each(|&v| city.add_new_value(v));
+
+    let mut exact = City::default();
+    fill(&mut exact);
+    let mut coarse = City::with_histogram_resolution(10);
+    fill(&mut coarse);
+
+    values.sort();
+    for p in [0.0, 0.01, 0.25, 0.5, 0.9, 0.99, 1.0] {
+        let expected = exact_percentile(&values, p);
+        assert_eq!(exact.percentile(p), expected, "p{p}");
+        // The top of the bucket of whole degrees holding the exact value
+        let approximate = coarse.percentile(p);
+        assert!(
+            (expected..expected + 1.0).contains(&approximate),
+            "p{p}: {approximate} vs {expected}"
+        );
+    }
+    assert_eq!(coarse.percentile(1.0), coarse.max());
+    // Every value occurs two or three times, the mode being the lowest of those occurring three times
+    assert_eq!(exact.mode_in(Unit::Celsius), exact_mode(&values));
+}
+
+fn exact_mode(sorted: &[i64]) -> f64 {
+    let mut runs = sorted.chunk_by(|a, b| a == b);
+    let longest = runs.clone().map(<[i64]>::len).max().unwrap();
+    runs.find(|run| run.len() == longest).unwrap()[0] as f64 / 10.0
+}
+
+#[test]
+fn empty_cities_have_no_percentiles() {
+    let city = City::default();
+    assert!(city.percentile(0.5).is_nan());
+    assert!(city.percentile(0.0).is_nan());
+    assert!(city.mode_in(Unit::Celsius).is_nan());
+}
+
+#[test]
+fn resolutions_belong_to_the_aggregation_and_its_partial_results() {
+    let lines = "Oslo;1.2\nOslo;1.2\nOslo;3.3\n";
+    let aggregate =
+        |config: &AggregateConfig| aggregate_reader(&mut lines.as_bytes(), config).unwrap();
+    let exact = aggregate(&AggregateConfig::new());
+    let coarse = aggregate(&AggregateConfig::new().histogram_resolution(10));
+    assert_eq!(exact.histogram_resolution(), 1);
+    assert_eq!(exact.get_city(b"Oslo").unwrap().percentile(0.5), 1.2);
+    assert_eq!(coarse.get_city(b"Oslo").unwrap().percentile(0.5), 2.0);
+
+    let mut bytes = vec![];
+    coarse.write_to(&mut bytes).unwrap();
+    let loaded = Citymap::read_from(&mut &bytes[..]).unwrap();
+    assert_eq!(loaded.histogram_resolution(), 10);
+    assert_eq!(loaded, coarse);
+}
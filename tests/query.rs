@@ -0,0 +1,26 @@
+//! Single stations have to be queryable by name, with the statistics the output reports.
+
+use onebrc::{aggregate_reader, AggregateConfig, CityStats};
+
+#[test]
+fn get_reports_the_rounded_stats_of_one_station() {
+    let mut map = aggregate_reader(
+        &mut "Oslo;-3.0\nLima;20.5\nOslo;4.6\nOslo;0.1\n".as_bytes(),
+        &AggregateConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        map.get("Oslo"),
+        Some(CityStats {
+            min: -3.0,
+            mean: 0.6,
+            max: 4.6,
+            count: 3,
+        })
+    );
+    assert_eq!(map.get("Lima").map(|stats| stats.count), Some(1));
+    assert_eq!(map.get("Bergen"), None);
+    // Looking a name up inserts it, but without values it has no stats yet
+    map.lookup(b"Bergen");
+    assert_eq!(map.get("Bergen"), None);
+}
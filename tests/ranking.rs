@@ -0,0 +1,46 @@
+//! `--top` and `--bottom` have to print the stations ranking highest and lowest, ties staying in name order.
+
+mod common;
+
+use std::process::Command;
+
+use common::TempFile;
+
+const LINES: &str = "Oslo;1.0\nLima;20.0\nKyiv;5.0\nAccra;20.0\nBern;-3.0\nOslo;3.0\n";
+
+fn stdout(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn top_and_bottom_pick_the_extremes() {
+    let file = TempFile::new("ranking", LINES);
+    let path = file.path();
+    assert_eq!(
+        stdout(&["--top", "3", path]),
+        "{Accra=20.0/20.0/20.0, Lima=20.0/20.0/20.0, Kyiv=5.0/5.0/5.0}\n"
+    );
+    assert_eq!(
+        stdout(&["--bottom", "2", path]),
+        "{Bern=-3.0/-3.0/-3.0, Oslo=1.0/2.0/3.0}\n"
+    );
+    assert_eq!(
+        stdout(&["--bottom", "2", "--by", "max", path]),
+        "{Bern=-3.0/-3.0/-3.0, Oslo=1.0/2.0/3.0}\n"
+    );
+    assert_eq!(
+        stdout(&["--bottom", "1", "--by", "min", path]),
+        "{Bern=-3.0/-3.0/-3.0}\n"
+    );
+
+    let both = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .args(["--top", "1", "--bottom", "1", path])
+        .output()
+        .unwrap();
+    assert!(!both.status.success());
+}
@@ -0,0 +1,37 @@
+//! Readers aggregated in parallel have to add up to their concatenation read in one go.
+
+use onebrc::{aggregate_reader, aggregate_readers, AggregateConfig, ParseError};
+
+#[test]
+fn parallel_readers_match_their_concatenation() {
+    let shards = (0..9)
+        .map(|shard| {
+            (0..200)
+                .map(|i| format!("Station {};{}.{}\n", (i * shard) % 11, i % 97 - 48, i % 10))
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>();
+    let expected =
+        aggregate_reader(&mut shards.concat().as_bytes(), &AggregateConfig::default()).unwrap();
+    for threads in [1, 2, 4, 20] {
+        let readers = shards.iter().map(|shard| shard.as_bytes()).collect();
+        let map = aggregate_readers(readers, &AggregateConfig::new().threads(threads)).unwrap();
+        assert_eq!(map, expected, "{threads} threads");
+    }
+    let none: Vec<&[u8]> = vec![];
+    assert_eq!(
+        aggregate_readers(none, &AggregateConfig::new())
+            .unwrap()
+            .len(),
+        0
+    );
+}
+
+#[test]
+fn a_malformed_reader_fails_the_run() {
+    let readers = vec![&b"Oslo;1.0\n"[..], b"Lima\n", b"Kyiv;2.0\n"];
+    assert!(matches!(
+        aggregate_readers(readers, &AggregateConfig::new().threads(2)),
+        Err(ParseError::MissingSeparator { .. })
+    ));
+}
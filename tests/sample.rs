@@ -0,0 +1,45 @@
+//! Sampling has to keep about the requested fraction of rows, and the same rows whatever the thread count.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{aggregate_file, AggregateConfig, Citymap, LineFormat};
+
+fn aggregate(path: &str, threads: usize, sample: Option<f64>) -> Citymap {
+    let format = LineFormat {
+        sample,
+        ..LineFormat::default()
+    };
+    aggregate_file(
+        path,
+        &AggregateConfig::new()
+            .threads(threads)
+            .buffer_bytes(4096)
+            .format(format),
+    )
+    .unwrap()
+}
+
+#[test]
+fn samples_are_deterministic_and_about_the_requested_size() {
+    let rows = (0..20_000)
+        .map(|i| format!("Station {};{}.{}", i % 7, i % 97 - 48, i % 10))
+        .collect::<Vec<_>>();
+    let file = TempFile::new("sample", rows.join("\n") + "\n");
+    let path = file.path();
+
+    let sample = aggregate(path, 1, Some(0.1));
+    assert!(
+        (1_800..2_200).contains(&sample.rows()),
+        "{} rows",
+        sample.rows()
+    );
+    for threads in [2, 5] {
+        assert_eq!(
+            aggregate(path, threads, Some(0.1)),
+            sample,
+            "{threads} threads"
+        );
+    }
+    assert_eq!(aggregate(path, 3, Some(1.0)), aggregate(path, 1, None));
+}
@@ -0,0 +1,73 @@
+//! Round trip of a saved map, which has to come back identical for checkpoints and partial results,
+//! and keep aggregating new data afterwards.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{aggregate_reader, AggregateConfig, Citymap};
+
+#[test]
+fn load_returns_the_saved_map() {
+    let input =
+        "Hamburg;12.0\nBulawayo;8.9\nSão Paulo;-0.3\nHamburg;34.2\nİzmir;99.9\nBulawayo;-99.9\n";
+    let map = aggregate_reader(&mut input.as_bytes(), &AggregateConfig::default()).unwrap();
+    let file = TempFile::new("save", "");
+    map.save(file.path()).unwrap();
+    assert_eq!(Citymap::load(file.path()).unwrap(), map);
+}
+
+#[test]
+fn load_rejects_truncated_maps() {
+    let map = aggregate_reader(
+        &mut "Hamburg;12.0\nBulawayo;8.9\n".as_bytes(),
+        &AggregateConfig::default(),
+    )
+    .unwrap();
+    let mut bytes = vec![];
+    map.write_to(&mut bytes).unwrap();
+    // The header, the first city and the very end, as histograms make the whole encoding large
+    let near_ends = (0..64).chain(bytes.len() - 64..bytes.len());
+    for len in near_ends {
+        assert!(
+            Citymap::read_from(&mut &bytes[..len]).is_err(),
+            "accepted {len} bytes"
+        );
+    }
+    assert_eq!(Citymap::read_from(&mut &bytes[..]).unwrap(), map);
+}
+
+#[test]
+fn overlong_names_are_rejected() {
+    let long = "x".repeat(1 << 17);
+    let map = aggregate_reader(
+        &mut format!("{long};1.0\n").as_bytes(),
+        &AggregateConfig::default(),
+    )
+    .unwrap();
+    assert!(map.write_to(&mut vec![]).is_err());
+
+    // A corrupted length far beyond any real name, with too little data behind it to be a name at all
+    let mut bytes = vec![];
+    Citymap::new().write_to(&mut bytes).unwrap();
+    bytes[20..28].copy_from_slice(&1u64.to_le_bytes());
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    let error = Citymap::read_from(&mut &bytes[..]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn ingesting_into_a_loaded_map_matches_one_pass() {
+    let (yesterday, today) = ("Hamburg;12.0\nBulawayo;8.9\n", "Hamburg;-4.2\nLima;21.0\n");
+    let file = TempFile::new("ingest", "");
+    aggregate_reader(&mut yesterday.as_bytes(), &AggregateConfig::default())
+        .unwrap()
+        .save(file.path())
+        .unwrap();
+    let mut map = Citymap::load(file.path()).unwrap();
+    map.ingest_reader(&mut today.as_bytes()).unwrap();
+    let both = yesterday.to_owned() + today;
+    assert_eq!(
+        map,
+        aggregate_reader(&mut both.as_bytes(), &AggregateConfig::default()).unwrap()
+    );
+}
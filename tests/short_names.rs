@@ -0,0 +1,26 @@
+//! Names shorter than a machine word are hashed and compared from partial reads, which must still
+//! keep every one of them apart, including those that are prefixes of each other.
+
+use onebrc::{aggregate_reader, AggregateConfig};
+
+#[test]
+fn short_names_stay_distinct() {
+    let names = [
+        "a", "b", "ab", "ba", "aa", "aaa", "aab", "abcdefg", "abcdefgh", "abcdefgi",
+    ];
+    let contents = names
+        .iter()
+        .enumerate()
+        .flat_map(|(i, name)| (0..=i).map(move |_| format!("{name};{i}.0\n")))
+        .collect::<String>();
+    let map = aggregate_reader(&mut contents.as_bytes(), &AggregateConfig::default()).unwrap();
+    assert_eq!(map.len(), names.len());
+    for (i, name) in names.iter().enumerate() {
+        let stats = map.get(name).unwrap();
+        assert_eq!(
+            (stats.count, stats.mean),
+            (i as u64 + 1, i as f64),
+            "{name}"
+        );
+    }
+}
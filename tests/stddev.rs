@@ -0,0 +1,34 @@
+//! The standard deviation is kept as exact sums, so merging partial cities has to give what a single pass does.
+
+use onebrc::City;
+
+#[test]
+fn merged_stddev_matches_a_single_pass() {
+    let values = (0..3_000)
+        .map(|i| (i * 7919) % 1999 - 999)
+        .collect::<Vec<i64>>();
+    let mut whole = City::default();
+    values.iter().for_each(|&v| whole.add_new_value(v));
+
+    let mut merged = City::default();
+    for part in values.chunks(701) {
+        let mut city = City::default();
+        part.iter().for_each(|&v| city.add_new_value(v));
+        merged.add_result(city);
+    }
+    assert_eq!(merged.std(), whole.std());
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<i64>() as f64 / n;
+    let variance = values
+        .iter()
+        .map(|&v| (v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    let expected = variance.sqrt() / 10.0;
+    assert!(
+        (whole.std() - expected).abs() < 1e-9,
+        "{} vs {expected}",
+        whole.std()
+    );
+}
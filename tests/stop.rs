@@ -0,0 +1,36 @@
+//! A requested stop has to cut aggregation short, with whatever was aggregated still adding up,
+//! and leave later aggregations with a flag of their own untouched.
+
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use common::TempFile;
+use onebrc::{aggregate_file, AggregateConfig};
+
+#[test]
+fn stop_leaves_the_rest_of_the_input() {
+    let rows = 400_000u64;
+    let contents = (0..rows)
+        .map(|i| format!("Station {};{}.{}\n", i % 17, i % 97, i % 10))
+        .collect::<String>();
+    let file = TempFile::new("stop", contents);
+    let path = file.path();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let aggregate = |threads, stop: &Arc<AtomicBool>| {
+        let config = AggregateConfig::new().threads(threads).stop(stop.clone());
+        aggregate_file(path, &config).unwrap().rows()
+    };
+    assert_eq!(aggregate(1, &stop), rows);
+
+    stop.store(true, Ordering::Relaxed);
+    // A single thread stops after its first read, multiple threads take no chunk at all
+    let single = aggregate(1, &stop);
+    assert!(0 < single && single < rows, "{single} rows");
+    assert_eq!(aggregate(3, &stop), 0);
+
+    let fresh = Arc::new(AtomicBool::new(false));
+    assert_eq!(aggregate(3, &fresh), rows);
+}
@@ -0,0 +1,36 @@
+//! NUL separated records have to aggregate exactly like the same records on separate lines.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{AggregateConfig, City, LineFormat};
+
+fn sorted(path: &str, threads: usize, format: LineFormat) -> Vec<(String, City)> {
+    common::sorted(
+        path,
+        &AggregateConfig::new().threads(threads).format(format),
+    )
+}
+
+#[test]
+fn nul_separated_records_match_newline_separated_ones() {
+    let rows = (0..500)
+        .map(|i| format!("Station {};{}.{}", i % 13, i % 97 - 48, i % 10))
+        .collect::<Vec<_>>();
+    let lines = TempFile::new("lines", rows.join("\n"));
+    let records = TempFile::new("records", rows.join("\0") + "\0");
+
+    let nul = LineFormat {
+        terminator: b'\0',
+        ..LineFormat::default()
+    };
+    let expected = sorted(lines.path(), 1, LineFormat::default());
+    assert_eq!(expected.len(), 13);
+    for threads in [1, 2, 5] {
+        assert_eq!(
+            sorted(records.path(), threads, nul),
+            expected,
+            "{threads} threads"
+        );
+    }
+}
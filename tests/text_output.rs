@@ -0,0 +1,92 @@
+//! Byte exact checks of the default output against the 1BRC reference format.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::TempFile;
+
+/// Everything the binary prints to stdout for a file with `contents`
+fn stdout(name: &str, contents: &str) -> String {
+    let file = TempFile::new(name, contents);
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .arg("st")
+        .arg(file.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn text_output_matches_reference() {
+    assert_eq!(
+        stdout("golden", "Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\nHamburg;34.2\nSt. John's;15.2\nBulawayo;-3.1\n"),
+        "{Bulawayo=-3.1/2.9/8.9, Hamburg=12.0/23.1/34.2, Palembang=38.8/38.8/38.8, St. John's=15.2/15.2/15.2}\n"
+    );
+}
+
+#[test]
+fn non_ascii_names_sort_by_code_point() {
+    assert_eq!(
+        stdout("sorted", "Zürich;1.0\nİzmir;2.0\nSão Paulo;3.0\nZagreb;4.0\nSydney;5.0\nÅrhus;6.0\n"),
+        "{Sydney=5.0/5.0/5.0, São Paulo=3.0/3.0/3.0, Zagreb=4.0/4.0/4.0, Zürich=1.0/1.0/1.0, Århus=6.0/6.0/6.0, İzmir=2.0/2.0/2.0}\n"
+    );
+}
+
+#[test]
+fn display_matches_the_printed_output() {
+    let contents = "Hamburg;12.0\nBulawayo;8.9\nHamburg;34.2\nBulawayo;-3.1\n";
+    let map = onebrc::aggregate_reader(
+        &mut contents.as_bytes(),
+        &onebrc::AggregateConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(map.to_string() + "\n", stdout("display", contents));
+    assert_eq!(
+        map.get_city(b"Hamburg").unwrap().to_string(),
+        "12.0/23.1/34.2"
+    );
+    assert_eq!(
+        format!("{map:.2}"),
+        "{Bulawayo=-3.10/2.90/8.90, Hamburg=12.00/23.10/34.20}"
+    );
+}
+
+#[test]
+fn empty_cities_display_without_panicking() {
+    assert_eq!(onebrc::City::default().to_string(), "NaN/NaN/NaN");
+    // Looking a name up inserts it, but the map leaves it out until it has a value
+    let mut map = onebrc::Citymap::<String>::new();
+    map.lookup(b"Oslo");
+    assert_eq!(map.to_string(), "{}");
+}
+
+/// Covers the rounding rules: halves go towards positive infinity and a mean rounding to zero prints no sign
+#[test]
+fn fixture_matches_expected_output() {
+    let fixture = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/measurements.txt"
+    );
+    let expected = include_str!("fixtures/measurements.out");
+    for threads in ["1", "4"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+            .args(["--threads", threads, fixture])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected,
+            "{threads} threads"
+        );
+    }
+    let map = onebrc::aggregate_reader(
+        &mut fs::read(fixture).unwrap().as_slice(),
+        &onebrc::AggregateConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(map.to_string() + "\n", expected);
+}
@@ -0,0 +1,63 @@
+//! Randomized check that splitting a file across workers gives the same result as a single pass.
+//! Files are kept small so that chunk boundaries land on every part of a line.
+
+mod common;
+
+use common::TempFile;
+use onebrc::{AggregateConfig, City};
+
+const CASES: u64 = 50;
+const THREADS: &[usize] = &[2, 3, 7];
+
+/// xorshift64*, enough to spread the generated cases
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) % bound
+    }
+}
+
+/// A valid measurement file, with a trailing newline only some of the time
+fn measurements(rng: &mut Rng) -> String {
+    let stations = 1 + rng.next(20);
+    let mut out = String::new();
+    for _ in 0..rng.next(300) {
+        let station = rng.next(stations);
+        // Names of very different lengths move the line boundaries around
+        let name = format!("{}{station}", "x".repeat(station as usize * 3));
+        let value = rng.next(1999) as i64 - 999;
+        let sign = if value < 0 { "-" } else { "" };
+        out += &format!("{name};{sign}{}.{}\n", value.abs() / 10, value.abs() % 10);
+    }
+    if rng.next(2) == 0 {
+        out.pop();
+    }
+    out
+}
+
+fn sorted(path: &str, threads: usize) -> Vec<(String, City)> {
+    common::sorted(path, &AggregateConfig::new().threads(threads))
+}
+
+#[test]
+fn multi_threaded_matches_single_thread() {
+    let file = TempFile::new("agree", "");
+    let path = file.path();
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for case in 0..CASES {
+        let contents = measurements(&mut rng);
+        file.write(&contents);
+        let expected = sorted(path, 1);
+        for &threads in THREADS {
+            assert_eq!(
+                sorted(path, threads),
+                expected,
+                "case {case} on {threads} threads differs for {contents:?}"
+            );
+        }
+    }
+}
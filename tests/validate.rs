@@ -0,0 +1,97 @@
+//! Validation has to find every line aggregation would fail on, without stopping at the first.
+
+mod common;
+
+use std::process::Command;
+
+use common::TempFile;
+use onebrc::{
+    validate_file, validate_reader, AggregateConfig, LineFormat, ParseError, MAX_REPORTED_ERRORS,
+};
+
+#[test]
+fn malformed_lines_are_counted_and_reported() {
+    let contents = "\u{FEFF}Oslo;1.0\nLima\n# comment\n\nKyiv;2.x\nRome;3.0;4.0\n";
+    let comments = LineFormat {
+        comment: Some(b'#'),
+        ..LineFormat::default()
+    };
+    let validation = validate_reader(&mut contents.as_bytes(), comments).unwrap();
+    assert_eq!(validation.lines, 6);
+    assert_eq!(validation.malformed, 3);
+    let lines = validation
+        .errors
+        .iter()
+        .map(|&(line, _)| line)
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [2, 5, 6]);
+    assert!(matches!(
+        validation.errors[0].1,
+        ParseError::MissingSeparator { offset: 12, .. }
+    ));
+
+    let many = "bad\n".repeat(MAX_REPORTED_ERRORS * 2);
+    let validation = validate_reader(&mut many.as_bytes(), LineFormat::default()).unwrap();
+    assert_eq!(validation.malformed, MAX_REPORTED_ERRORS as u64 * 2);
+    assert_eq!(validation.errors.len(), MAX_REPORTED_ERRORS);
+}
+
+#[test]
+fn exit_code_reflects_the_outcome() {
+    let good = TempFile::new("valid", "Oslo;1.0\nLima;2.0\n");
+    let bad = TempFile::new("invalid", "Oslo;1.0\nLima;\n");
+    let validate = |file: &TempFile| {
+        Command::new(env!("CARGO_BIN_EXE_onebrc"))
+            .arg("--validate")
+            .arg(file.path())
+            .output()
+            .unwrap()
+    };
+    let output = validate(&good);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .ends_with(": 0 of 2 lines malformed\n"));
+    assert_eq!(validate(&bad).status.code(), Some(1));
+}
+
+#[test]
+fn unreadable_inputs_are_errors() {
+    assert!(validate_file(
+        "/nonexistent/onebrc/measurements.txt",
+        &AggregateConfig::new()
+    )
+    .is_err());
+    // A read failing midway, as on a disk error, ends the validation
+    struct Failing;
+    impl std::io::Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk on fire"))
+        }
+    }
+    let mut input = std::io::BufReader::new(Failing);
+    assert!(validate_reader(&mut input, LineFormat::default()).is_err());
+}
+
+#[test]
+fn only_the_command_line_skips_comments_by_default() {
+    let contents = "#hashtag;1.0\nOslo;2.0\n";
+    let validation = validate_reader(&mut contents.as_bytes(), LineFormat::default()).unwrap();
+    assert!(validation.is_valid());
+    let map = onebrc::aggregate_reader(
+        &mut contents.as_bytes(),
+        &onebrc::AggregateConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(map.get("#hashtag").map(|stats| stats.count), Some(1));
+
+    let file = TempFile::new("comments", contents);
+    let output = Command::new(env!("CARGO_BIN_EXE_onebrc"))
+        .arg(file.path())
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{Oslo=2.0/2.0/2.0}\n"
+    );
+}
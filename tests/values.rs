@@ -0,0 +1,87 @@
+//! Values outside the fixed format the challenge guarantees, as written by other exports.
+
+use onebrc::{parse_line, ParseError};
+
+fn value(line: &str) -> Result<i64, ParseError> {
+    parse_line(line.as_bytes()).map(|(_, value)| value)
+}
+
+#[test]
+fn explicit_plus_sign_and_whitespace_are_accepted() {
+    assert_eq!(value("Lima;+1.2").unwrap(), 12);
+    assert_eq!(value("Lima; 1.2").unwrap(), 12);
+    assert_eq!(value("Lima;1.2 ").unwrap(), 12);
+    assert_eq!(value("Lima; +21.0 ").unwrap(), 210);
+    assert_eq!(value("Lima;-1.2\r").unwrap(), -12);
+}
+
+#[test]
+fn integer_values_are_whole_degrees() {
+    // The fixed format of the challenge and the generic parser taking everything else
+    assert_eq!(value("Lima;12.3").unwrap(), 123);
+    assert_eq!(value("Lima;-1.5").unwrap(), -15);
+    assert_eq!(value("Lima;30").unwrap(), 300);
+    assert_eq!(value("Lima;30.").unwrap(), 300);
+    assert_eq!(value("Lima;-7").unwrap(), -70);
+    assert_eq!(value("Lima;0").unwrap(), 0);
+    assert_eq!(value("Lima;.5").unwrap(), 5);
+    assert!(matches!(
+        value("Lima;12.34"),
+        Err(ParseError::BadDigit { byte: b'4', .. })
+    ));
+}
+
+#[test]
+fn misplaced_signs_and_other_bytes_are_rejected() {
+    for line in [
+        "Lima;1+2",
+        "Lima;++1.2",
+        "Lima;+-1.2",
+        "Lima;1. 2",
+        "Lima;1,2",
+        "Lima;+",
+        "Lima;1-2",
+        "Lima;5-",
+        "Lima;1.2.3",
+        "Lima;1.25",
+    ] {
+        assert!(value(line).is_err(), "{line:?} parsed");
+    }
+    for (line, byte) in [
+        ("Lima;1-2", b'-'),
+        ("Lima;5-", b'-'),
+        ("Lima;1.2.3", b'.'),
+        ("Lima;1.25", b'5'),
+    ] {
+        assert!(
+            matches!(value(line), Err(ParseError::BadDigit { byte: b, .. }) if b == byte),
+            "{line:?}"
+        );
+    }
+    // A trailing point is a whole degree, not an error
+    assert_eq!(value("Lima;30.").unwrap(), 300);
+    assert!(matches!(
+        value("Lima;1+2"),
+        Err(ParseError::BadDigit { byte: b'+', .. })
+    ));
+    assert!(matches!(
+        value("Lima;+"),
+        Err(ParseError::EmptyValue { .. })
+    ));
+}
+
+#[test]
+fn values_beyond_the_challenge_range_are_accepted() {
+    assert_eq!(value("Lima;1234.5").unwrap(), 12345);
+    assert_eq!(value("Lima;-5000").unwrap(), -50000);
+    assert_eq!(value("Lima;303700049.9").unwrap(), onebrc::MAX_ABS_VALUE);
+    // Only digit runs that would overflow the sums are rejected
+    assert!(matches!(
+        value("Lima;303700050.0"),
+        Err(ParseError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        value(&format!("Lima;{}", "9".repeat(40))),
+        Err(ParseError::OutOfRange { .. })
+    ));
+}